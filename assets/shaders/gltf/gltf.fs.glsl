@@ -1,6 +1,7 @@
 #version 330 core
 
 in vec2 texCoords;
+in vec4 vertexColor;
 out vec4 color;
 
 uniform sampler2D texture_diffuse1;
@@ -12,7 +13,7 @@ void main()
   // if(texture_color.a < 0.5) {
   //   discard;
   // }
-  color = vec4(texture_color);
+  color = texture_color * vertexColor;
   // color = base_color;
   // color = texture(texture_diffuse1, texCoords);
 }
@@ -2,18 +2,28 @@
 layout (location = 0) in vec3 v_position;
 layout (location = 1) in vec3 v_normal;
 layout (location = 2) in vec2 v_texCoords;
+layout (location = 7) in vec4 v_color;
 
 out vec3 position;
 out vec3 normal;
 out vec2 texCoords;
+out vec4 vertexColor;
 
 uniform mat4 model;
 uniform mat4 view;
 uniform mat4 projection;
 
+// One bit per attribute location the primitive provides
+uniform int u_attributes;
+const int COLOR_0 = 1 << 7;
+
 void main()
 {
     texCoords = v_texCoords;
+
+    // Untinted when the primitive has no vertex colors
+    vertexColor = (u_attributes & COLOR_0) != 0 ? v_color : vec4(1.0);
+
     position = vec3(model * vec4(v_position, 1.0));
 
     // TODO: compute this on the cpu and pass it along
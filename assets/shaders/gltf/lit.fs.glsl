@@ -43,6 +43,7 @@ struct SpotLight {
 in vec3 position;
 in vec3 normal;
 in vec2 texCoords;
+in vec4 vertexColor;
 out vec4 color;
 
 uniform Material material;
@@ -66,7 +67,7 @@ void main()
     result += calculate_point_light(point_lights[i], norm, position, view_dir);
   result += calculate_spot_light(spotlight, norm, position, view_dir);
 
-  color = vec4(result, 1.0);
+  color = vec4(result, 1.0) * vertexColor;
 }
 
 vec3 calculate_directional_light(DirectionalLight light, vec3 normal, vec3 view_direction)
@@ -2,14 +2,21 @@
 layout (location = 0) in vec3 v_position;
 layout (location = 1) in vec3 v_normal;
 layout (location = 2) in vec2 v_texCoords;
-layout (location = 3) in vec4 a_joint;
+layout (location = 3) in uvec4 a_joint;
 layout (location = 4) in vec4 a_weight;
+layout (location = 7) in vec4 v_color;
 
 uniform mat4 u_jointMatrix[2];
 
+// One bit per attribute location the primitive provides
+uniform int u_attributes;
+const int JOINTS_0 = 1 << 3;
+const int COLOR_0 = 1 << 7;
+
 out vec3 position;
 out vec3 normal;
 out vec2 texCoords;
+out vec4 vertexColor;
 
 uniform mat4 model;
 uniform mat4 view;
@@ -17,14 +24,20 @@ uniform mat4 projection;
 
 void main()
 {
-    mat4 skinMatrix =
-      a_weight.x * u_jointMatrix[int(a_joint.x)] +
-      a_weight.y * u_jointMatrix[int(a_joint.y)] +
-      a_weight.z * u_jointMatrix[int(a_joint.z)] +
-      a_weight.w * u_jointMatrix[int(a_joint.w)];
+    mat4 skinMatrix = mat4(1.0);
+    if ((u_attributes & JOINTS_0) != 0) {
+      skinMatrix =
+        a_weight.x * u_jointMatrix[a_joint.x] +
+        a_weight.y * u_jointMatrix[a_joint.y] +
+        a_weight.z * u_jointMatrix[a_joint.z] +
+        a_weight.w * u_jointMatrix[a_joint.w];
+    }
 
     texCoords = v_texCoords;
 
+    // Untinted when the primitive has no vertex colors
+    vertexColor = (u_attributes & COLOR_0) != 0 ? v_color : vec4(1.0);
+
     // TODO: compute this on the cpu and pass it along
     normal = mat3(transpose(inverse(model))) * v_normal;
 
@@ -0,0 +1,192 @@
+#version 330 core
+
+struct Material {
+  vec4 base_color_factor;
+  sampler2D base_color_texture;
+  bool has_base_color_texture;
+  int base_color_tex_coord;
+
+  float metallic_factor;
+  float roughness_factor;
+  // Metalness is in the blue channel and roughness in the green channel
+  sampler2D metallic_roughness_texture;
+  bool has_metallic_roughness_texture;
+  int metallic_roughness_tex_coord;
+
+  sampler2D normal_texture;
+  bool has_normal_texture;
+  int normal_tex_coord;
+  float normal_scale;
+
+  sampler2D occlusion_texture;
+  bool has_occlusion_texture;
+  int occlusion_tex_coord;
+  float occlusion_strength;
+
+  sampler2D emissive_texture;
+  bool has_emissive_texture;
+  int emissive_tex_coord;
+  vec3 emissive_factor;
+
+  int alpha_mode;
+  float alpha_cutoff;
+  bool double_sided;
+};
+
+struct DirectionalLight {
+  vec3 direction;
+  vec3 color;
+};
+
+struct PointLight {
+  vec3 position;
+  vec3 color;
+};
+
+const int ALPHA_MODE_OPAQUE = 0;
+const int ALPHA_MODE_MASK = 1;
+
+const float PI = 3.14159265359;
+
+#define NUMBER_OF_POINT_LIGHTS 2
+
+in vec3 position;
+in vec3 normal;
+in vec4 tangent;
+in vec2 texCoords0;
+in vec2 texCoords1;
+in vec4 vertexColor;
+
+out vec4 color;
+
+uniform Material material;
+uniform vec3 view_pos;
+uniform vec3 ambient_color;
+uniform DirectionalLight directional_light;
+uniform PointLight point_lights[NUMBER_OF_POINT_LIGHTS];
+
+vec2 texCoords(int set)
+{
+  return set == 1 ? texCoords1 : texCoords0;
+}
+
+vec3 surfaceNormal()
+{
+  vec3 n = normalize(normal);
+  if (material.double_sided && !gl_FrontFacing) {
+    n = -n;
+  }
+  if (!material.has_normal_texture) {
+    return n;
+  }
+
+  vec2 uv = texCoords(material.normal_tex_coord);
+  vec3 t;
+  vec3 b;
+  if (tangent.xyz != vec3(0.0)) {
+    t = normalize(tangent.xyz - n * dot(n, tangent.xyz));
+    b = cross(n, t) * tangent.w;
+  } else {
+    vec3 dp1 = dFdx(position);
+    vec3 dp2 = dFdy(position);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    t = normalize(dp1 * duv2.t - dp2 * duv1.t);
+    b = normalize(dp2 * duv1.s - dp1 * duv2.s);
+    t = normalize(t - n * dot(n, t));
+  }
+
+  vec3 mapped = texture(material.normal_texture, uv).rgb * 2.0 - 1.0;
+  mapped.xy *= material.normal_scale;
+  return normalize(mat3(t, b, n) * mapped);
+}
+
+float distributionGGX(float nDotH, float alpha)
+{
+  float alphaSquared = alpha * alpha;
+  float denominator = nDotH * nDotH * (alphaSquared - 1.0) + 1.0;
+  return alphaSquared / (PI * denominator * denominator);
+}
+
+float geometrySmith(float nDotV, float nDotL, float alpha)
+{
+  float k = alpha / 2.0;
+  float view = nDotV / (nDotV * (1.0 - k) + k);
+  float light = nDotL / (nDotL * (1.0 - k) + k);
+  return view * light;
+}
+
+vec3 fresnelSchlick(float vDotH, vec3 f0)
+{
+  return f0 + (1.0 - f0) * pow(1.0 - vDotH, 5.0);
+}
+
+// Cook-Torrance specular with a Lambertian diffuse term
+vec3 shade(vec3 n, vec3 v, vec3 l, vec3 radiance, vec3 albedo, float metallic, float roughness)
+{
+  vec3 h = normalize(v + l);
+  float nDotL = max(dot(n, l), 0.0);
+  float nDotV = max(dot(n, v), 0.0001);
+  float nDotH = max(dot(n, h), 0.0);
+  float vDotH = max(dot(v, h), 0.0);
+
+  float alpha = roughness * roughness;
+  vec3 f0 = mix(vec3(0.04), albedo, metallic);
+  vec3 fresnel = fresnelSchlick(vDotH, f0);
+  vec3 specular = fresnel * distributionGGX(nDotH, alpha) * geometrySmith(nDotV, nDotL, alpha)
+    / (4.0 * nDotV * max(nDotL, 0.0001));
+  vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / PI;
+  return (diffuse + specular) * radiance * nDotL;
+}
+
+void main()
+{
+  vec4 baseColor = material.base_color_factor * vertexColor;
+  if (material.has_base_color_texture) {
+    baseColor *= texture(material.base_color_texture, texCoords(material.base_color_tex_coord));
+  }
+
+  if (material.alpha_mode == ALPHA_MODE_MASK && baseColor.a < material.alpha_cutoff) {
+    discard;
+  }
+
+  float metallic = material.metallic_factor;
+  float roughness = material.roughness_factor;
+  if (material.has_metallic_roughness_texture) {
+    vec4 sampled = texture(material.metallic_roughness_texture,
+                           texCoords(material.metallic_roughness_tex_coord));
+    metallic *= sampled.b;
+    roughness *= sampled.g;
+  }
+  metallic = clamp(metallic, 0.0, 1.0);
+  roughness = clamp(roughness, 0.04, 1.0);
+
+  vec3 n = surfaceNormal();
+  vec3 v = normalize(view_pos - position);
+
+  vec3 result = shade(n, v, normalize(-directional_light.direction), directional_light.color,
+                      baseColor.rgb, metallic, roughness);
+  for (int i = 0; i < NUMBER_OF_POINT_LIGHTS; i++) {
+    vec3 toLight = point_lights[i].position - position;
+    float lightDistance = length(toLight);
+    vec3 radiance = point_lights[i].color / (lightDistance * lightDistance);
+    result += shade(n, v, toLight / lightDistance, radiance, baseColor.rgb, metallic, roughness);
+  }
+
+  float occlusion = 1.0;
+  if (material.has_occlusion_texture) {
+    float sampled = texture(material.occlusion_texture, texCoords(material.occlusion_tex_coord)).r;
+    occlusion = 1.0 + material.occlusion_strength * (sampled - 1.0);
+  }
+  result += ambient_color * baseColor.rgb * occlusion;
+
+  vec3 emissive = material.emissive_factor;
+  if (material.has_emissive_texture) {
+    emissive *= texture(material.emissive_texture, texCoords(material.emissive_tex_coord)).rgb;
+  }
+  result += emissive;
+
+  // Opaque materials ignore alpha entirely
+  float alpha = material.alpha_mode == ALPHA_MODE_OPAQUE ? 1.0 : baseColor.a;
+  color = vec4(result, alpha);
+}
@@ -0,0 +1,62 @@
+#version 330 core
+layout (location = 0) in vec3 v_position;
+layout (location = 1) in vec3 v_normal;
+layout (location = 2) in vec2 v_texCoords;
+layout (location = 3) in uvec4 a_joint;
+layout (location = 4) in vec4 a_weight;
+layout (location = 5) in vec4 v_tangent;
+layout (location = 6) in vec2 v_texCoords1;
+layout (location = 7) in vec4 v_color;
+
+uniform mat4 u_jointMatrix[64];
+
+// One bit per attribute location the primitive provides
+uniform int u_attributes;
+const int JOINTS_0 = 1 << 3;
+const int TANGENT = 1 << 5;
+const int TEXCOORD_1 = 1 << 6;
+const int COLOR_0 = 1 << 7;
+
+out vec3 position;
+out vec3 normal;
+out vec4 tangent;
+out vec2 texCoords0;
+out vec2 texCoords1;
+out vec4 vertexColor;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+void main()
+{
+    mat4 skinMatrix = mat4(1.0);
+    if ((u_attributes & JOINTS_0) != 0) {
+      skinMatrix =
+        a_weight.x * u_jointMatrix[a_joint.x] +
+        a_weight.y * u_jointMatrix[a_joint.y] +
+        a_weight.z * u_jointMatrix[a_joint.z] +
+        a_weight.w * u_jointMatrix[a_joint.w];
+    }
+    mat4 world = model * skinMatrix;
+
+    texCoords0 = v_texCoords;
+
+    // Falls back to the first set when the primitive has no second one
+    texCoords1 = (u_attributes & TEXCOORD_1) != 0 ? v_texCoords1 : v_texCoords;
+
+    // Untinted when the primitive has no vertex colors
+    vertexColor = (u_attributes & COLOR_0) != 0 ? v_color : vec4(1.0);
+
+    // TODO: compute this on the cpu and pass it along
+    mat3 normalMatrix = mat3(transpose(inverse(world)));
+    normal = normalMatrix * v_normal;
+
+    // A zero tangent tells the fragment shader to derive one from screen space derivatives
+    tangent = (u_attributes & TANGENT) != 0
+      ? vec4(mat3(world) * v_tangent.xyz, v_tangent.w)
+      : vec4(0.0);
+
+    position = vec3(world * vec4(v_position, 1.0));
+    gl_Position = projection * view * vec4(position, 1.0);
+}
@@ -0,0 +1,153 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gltf::animation::util::ReadOutputs;
+use nalgebra::Quaternion;
+use nalgebra_glm as glm;
+use sepia::gltf::GltfAsset;
+
+const WOLF: &str = "assets/models/Wolf.glb";
+
+enum Keyframes {
+    Translations(Vec<glm::Vec3>),
+    Rotations(Vec<glm::Vec4>),
+    Scales(Vec<glm::Vec3>),
+    MorphTargetWeights,
+}
+
+// A channel as the importer stored it before targets were resolved at load
+struct ScannedChannel {
+    node_index: usize,
+    inputs: Vec<f32>,
+    keyframes: Keyframes,
+    previous_key: usize,
+    previous_time: f32,
+}
+
+fn scanned_channels(path: &str) -> Vec<Vec<ScannedChannel>> {
+    let (gltf, buffers, _) = gltf::import(path).expect("Couldn't import file!");
+    gltf.animations()
+        .map(|animation| {
+            animation
+                .channels()
+                .map(|channel| {
+                    let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                    let keyframes = match reader.read_outputs().unwrap() {
+                        ReadOutputs::Translations(translations) => {
+                            Keyframes::Translations(translations.map(glm::Vec3::from).collect())
+                        }
+                        ReadOutputs::Rotations(rotations) => Keyframes::Rotations(
+                            rotations.into_f32().map(glm::Vec4::from).collect(),
+                        ),
+                        ReadOutputs::Scales(scales) => {
+                            Keyframes::Scales(scales.map(glm::Vec3::from).collect())
+                        }
+                        ReadOutputs::MorphTargetWeights(_) => Keyframes::MorphTargetWeights,
+                    };
+                    ScannedChannel {
+                        node_index: channel.target().node().index(),
+                        inputs: reader.read_inputs().unwrap().collect(),
+                        keyframes,
+                        previous_key: 0,
+                        previous_time: 0.0,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// The original animate: every channel searches every node of every graph for its target,
+// then scans forward from the previous key for the next one
+// Global transforms are updated afterwards, as the current animate does
+fn animate_by_scan(asset: &mut GltfAsset, animations: &mut [Vec<ScannedChannel>], seconds: f32) {
+    for channels in animations.iter_mut() {
+        for channel in channels.iter_mut() {
+            for scene in asset.scenes.iter_mut() {
+                for graph in scene.node_graphs.iter_mut() {
+                    for node_index in graph.node_indices() {
+                        if graph[node_index].index != channel.node_index {
+                            continue;
+                        }
+                        let mut time = seconds % channel.inputs.last().unwrap();
+                        let first_input = channel.inputs.first().unwrap();
+                        if time < *first_input {
+                            time = *first_input;
+                        }
+
+                        if channel.previous_time > time {
+                            channel.previous_key = 0;
+                        }
+                        channel.previous_time = time;
+
+                        let mut next_key: usize = 0;
+                        for index in channel.previous_key..channel.inputs.len() {
+                            if time <= channel.inputs[index] {
+                                next_key = nalgebra::clamp(index, 1, channel.inputs.len() - 1);
+                                break;
+                            }
+                        }
+                        channel.previous_key = nalgebra::clamp(next_key - 1, 0, next_key);
+
+                        let key_delta =
+                            channel.inputs[next_key] - channel.inputs[channel.previous_key];
+                        let normalized_time =
+                            (time - channel.inputs[channel.previous_key]) / key_delta;
+
+                        let node = &mut graph[node_index];
+                        match &channel.keyframes {
+                            Keyframes::Translations(translations) => {
+                                let start = translations[channel.previous_key];
+                                let end = translations[next_key];
+                                node.animation_transform.translation =
+                                    Some(start.lerp(&end, normalized_time));
+                            }
+                            Keyframes::Rotations(rotations) => {
+                                let start = rotations[channel.previous_key];
+                                let end = rotations[next_key];
+                                let start = Quaternion::new(start[3], start[0], start[1], start[2]);
+                                let end = Quaternion::new(end[3], end[0], end[1], end[2]);
+                                node.animation_transform.rotation =
+                                    Some(start.lerp(&end, normalized_time));
+                            }
+                            Keyframes::Scales(scales) => {
+                                let start = scales[channel.previous_key];
+                                let end = scales[next_key];
+                                node.animation_transform.scale =
+                                    Some(start.lerp(&end, normalized_time));
+                            }
+                            Keyframes::MorphTargetWeights => {}
+                        }
+                        node.mark_dirty();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    for scene in asset.scenes.iter_mut() {
+        scene.update_global_transforms();
+    }
+}
+
+fn animate_wolf(c: &mut Criterion) {
+    let mut asset = GltfAsset::import(WOLF);
+    let mut time = 0.0;
+    c.bench_function("animate", |b| {
+        b.iter(|| {
+            time += 1.0 / 60.0;
+            asset.animate(black_box(time));
+        })
+    });
+
+    let mut asset = GltfAsset::import(WOLF);
+    let mut animations = scanned_channels(WOLF);
+    let mut time = 0.0;
+    c.bench_function("animate_by_scan", |b| {
+        b.iter(|| {
+            time += 1.0 / 60.0;
+            animate_by_scan(&mut asset, &mut animations, black_box(time));
+        })
+    });
+}
+
+criterion_group!(benches, animate_wolf);
+criterion_main!(benches);
@@ -81,7 +81,8 @@ impl State for MainState {
         self.vbo = Buffer::new(BufferKind::Array);
         self.vbo.add_data(VERTEX_POSITIONS);
         self.vbo.upload(&self.vao, DrawingHint::StaticDraw);
-        self.vao.configure_attribute(0, 3, 3, 0);
+        self.vao
+            .configure_layout(VertexLayout::new().float(Semantic::Position, 3));
 
         unsafe {
             gl::Enable(gl::CULL_FACE);
@@ -187,8 +188,7 @@ impl State for MainState {
 }
 
 fn main() {
-    let mut state = MainState::default();
-    let mut state_machine: Vec<&mut dyn State> = Vec::new();
-    state_machine.push(&mut state);
+    let mut state_machine: Vec<Box<dyn State>> = Vec::new();
+    state_machine.push(Box::new(MainState::default()));
     App::new(state_machine).run();
 }
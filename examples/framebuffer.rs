@@ -138,8 +138,7 @@ impl State for MainState {
 }
 
 fn main() {
-    let mut state = MainState::default();
-    let mut state_machine: Vec<&mut dyn State> = Vec::new();
-    state_machine.push(&mut state);
+    let mut state_machine: Vec<Box<dyn State>> = Vec::new();
+    state_machine.push(Box::new(MainState::default()));
     App::new(state_machine).run();
 }
@@ -1,8 +1,6 @@
 use nalgebra_glm as glm;
-use petgraph::{prelude::*, visit::Dfs};
 use sepia::app::*;
-use sepia::{camera::*, gltf::*, shaderprogram::*, skybox::*};
-use std::ptr;
+use sepia::{camera::*, gltf::*, renderqueue::*, shaderprogram::*, skybox::*};
 
 // TODO: Eventually remove default derivations where not necessary
 #[derive(Default)]
@@ -20,8 +18,8 @@ impl State for MainState {
     fn initialize(&mut self) {
         self.shader_program = ShaderProgram::new();
         self.shader_program
-            .vertex_shader_file("assets/shaders/gltf/gltf.vs.glsl")
-            .fragment_shader_file("assets/shaders/gltf/lit.fs.glsl")
+            .vertex_shader_file("assets/shaders/gltf/pbr.vs.glsl")
+            .fragment_shader_file("assets/shaders/gltf/pbr.fs.glsl")
             .link();
         self.solid_color_program = ShaderProgram::new();
         self.solid_color_program
@@ -43,9 +41,11 @@ impl State for MainState {
             "assets/textures/skyboxes/bluemountains/front.jpg".to_string(),
         ]);
 
-        // self.asset = Some(GltfAsset::from_file("assets/models/RiggedSimple.glb"));
         self.asset = Some(GltfAsset::from_file("assets/models/Duck/Duck.gltf"));
 
+        // The pbr shader outputs linear color
+        set_gamma_correction(true);
+
         unsafe {
             gl::Enable(gl::CULL_FACE);
             gl::Enable(gl::DEPTH_TEST);
@@ -131,316 +131,149 @@ impl State for MainState {
 
         let view = self.camera.view_matrix();
 
-        // Render the asset's scene graphs
-        let asset = self.asset.as_mut().expect("Couldn't get asset!");
-        for scene in asset.scenes.iter() {
-            for graph in scene.node_graphs.iter() {
-                let mut dfs = Dfs::new(&graph, NodeIndex::new(0));
-                while let Some(node_index) = dfs.next(&graph) {
-                    let global_transform = calculate_global_transform(node_index, graph);
-
-                    // Skinning
-                    if let Some(skin) = graph[node_index].skin.as_ref() {
-                        for (index, joint) in skin.joints.iter().enumerate() {
-                            let joint_global_transform =
-                                calculate_global_transform(NodeIndex::new(joint.index), &graph);
-
-                            let parent_global_transform =
-                                calculate_global_transform(node_index, &graph);
+        // Lights
+        self.shader_program
+            .set_uniform_vec3("ambient_color", glm::vec3(0.03, 0.03, 0.03).as_slice());
+        self.shader_program.set_uniform_vec3(
+            "directional_light.direction",
+            glm::vec3(-0.2, -1.0, -0.3).as_slice(),
+        );
+        self.shader_program.set_uniform_vec3(
+            "directional_light.color",
+            glm::vec3(3.0, 2.8, 2.6).as_slice(),
+        );
 
-                            // TODO: This must not be correct, fix it
-                            let joint_matrix =
-                            // Inverse transform of the node the mesh is attached to
-                                // glm::inverse(&parent_global_transform) *
-                            // Current global transform of the joint node
-                                // joint_global_transform *
-                            // Transform of the joint's inverse bind matrix
-                                // joint.inverse_bind_matrix;
-                                glm::Mat4::identity();
+        let point_light_pos1 = glm::vec3(10.0, 15.0, 45.0);
+        let point_light_color1 = glm::vec3(0.0, 1.0, 0.5);
+        self.shader_program
+            .set_uniform_vec3("point_lights[0].position", point_light_pos1.as_slice());
+        self.shader_program.set_uniform_vec3(
+            "point_lights[0].color",
+            (point_light_color1 * 500.0).as_slice(),
+        );
 
-                            self.shader_program.set_uniform_matrix4x4(
-                                &format!("u_jointMatrix[{}]", index),
-                                joint_matrix.as_slice(),
-                            );
-                        }
-                    }
+        let point_light_pos2 = glm::vec3(-10.3, 15.3, -10.0);
+        let point_light_color2 = glm::vec3(1.0, 0.0, 0.0);
+        self.shader_program
+            .set_uniform_vec3("point_lights[1].position", point_light_pos2.as_slice());
+        self.shader_program.set_uniform_vec3(
+            "point_lights[1].color",
+            (point_light_color2 * 500.0).as_slice(),
+        );
 
-                    // Render with the given transform
-                    if let Some(mesh) = graph[node_index].mesh.as_ref() {
-                        for primitive_info in mesh.primitives.iter() {
-                            if let Some(material_index) = primitive_info.material_index {
-                                let material = asset.lookup_material(material_index);
-                                let pbr = material.pbr_metallic_roughness();
-                                //let base_color = glm::Vec4::from(pbr.base_color_factor()).xyz();
-                                if !asset.texture_ids.is_empty() {
-                                    let base_color_index = pbr
-                                        .base_color_texture()
-                                        .expect("Couldn't get base color texture!")
-                                        .texture()
-                                        .index();
-                                    unsafe {
-                                        gl::BindTexture(
-                                            gl::TEXTURE_2D,
-                                            asset.texture_ids[base_color_index],
-                                        );
-                                    }
-                                };
-                                self.shader_program
-                                    .set_uniform_int("material.diffuse_texture", 0);
-                                self.shader_program
-                                    .set_uniform_float("material.shininess", 32.0);
-                            }
+        self.shader_program
+            .set_uniform_vec3("view_pos", &self.camera.position.as_slice());
+        self.shader_program
+            .set_uniform_matrix4x4("view", view.as_slice());
+        self.shader_program
+            .set_uniform_matrix4x4("projection", projection.as_slice());
 
-                            // Flashlight settings
-                            self.shader_program.set_uniform_vec3(
-                                "spotlight.position",
-                                &self.camera.position.as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "spotlight.direction",
-                                &self.camera.front.as_slice(),
-                            );
-                            self.shader_program
-                                .set_uniform_float("spotlight.cutOff", 12.5_f32.to_radians().cos());
-                            self.shader_program.set_uniform_float(
-                                "spotlight.outerCutOff",
-                                17.5_f32.to_radians().cos(),
-                            );
-                            self.shader_program
-                                .set_uniform_float("spotlight.constant", 1.0);
-                            self.shader_program
-                                .set_uniform_float("spotlight.linear", 0.007);
-                            self.shader_program
-                                .set_uniform_float("spotlight.quadratic", 0.0002);
-                            self.shader_program.set_uniform_vec3(
-                                "spotlight.ambient",
-                                &glm::vec3(0.3, 0.24, 0.14).as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "spotlight.diffuse",
-                                &glm::vec3(0.7, 0.42, 0.26).as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "spotlight.specular",
-                                &glm::vec3(0.5, 0.5, 0.5).as_slice(),
-                            );
+        self.solid_color_program
+            .set_uniform_matrix4x4("view", view.as_slice());
+        self.solid_color_program
+            .set_uniform_matrix4x4("projection", projection.as_slice());
+        self.solid_color_program
+            .set_uniform_vec3("highlight", glm::vec3(0.04, 0.28, 0.26).as_slice());
 
-                            // Directional Light
-                            self.shader_program.set_uniform_vec3(
-                                "directional_light.direction",
-                                &glm::vec3(-0.2, -1.0, -0.3).as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "directional_light.ambient",
-                                &glm::vec3(0.3, 0.24, 0.14).as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "directional_light.diffuse",
-                                &glm::vec3(0.7, 0.42, 0.26).as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "directional_light.specular",
-                                &glm::vec3(0.5, 0.5, 0.5).as_slice(),
-                            );
+        // Queue every instance in the grid on its own, so blended primitives are drawn last,
+        // back to front by where each instance actually is
+        let asset = self.asset.as_ref().expect("Couldn't get asset!");
+        let mut queue = RenderQueue::new();
+        for row in 0..10 {
+            for column in 0..10 {
+                let model = glm::translate(
+                    &glm::Mat4::identity(),
+                    &glm::vec3(row as f32 * -10.0, 0.0, column as f32 * 10.0),
+                ) * glm::scale(&glm::Mat4::identity(), &glm::vec3(6.0, 6.0, 6.0));
+                asset.queue_primitives(&mut queue, &model, &view);
+            }
+        }
 
-                            // Point light 1
-                            let point_light_pos1 = glm::vec3(10.0, 15.0, 45.0);
-                            let point_light_color1 = glm::vec3(0.0, 1.0, 0.5);
-                            let point_light_color1_ambient = point_light_color1 * 0.1;
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[0].position",
-                                &point_light_pos1.as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[0].ambient",
-                                &point_light_color1_ambient.as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[0].diffuse",
-                                &point_light_color1.as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[0].specular",
-                                &point_light_color1.as_slice(),
-                            );
-                            self.shader_program
-                                .set_uniform_float("point_lights[0].constant", 1.0);
-                            self.shader_program
-                                .set_uniform_float("point_lights[0].linear", 0.007);
-                            self.shader_program
-                                .set_uniform_float("point_lights[0].quadratic", 0.0002);
+        unsafe {
+            gl::StencilFunc(gl::ALWAYS, 1, 0xFF); // all fragments should update the stencil buffer
+            gl::StencilMask(0xFF); // enable writing to the stencil buffer
+        }
+        queue.render(|instance| {
+            let node = asset.node(instance.handle.scene_index, instance.handle.node);
+            let primitive_info = asset.primitive(instance.handle);
 
-                            // Point light 2
-                            let point_light_pos2 = glm::vec3(-10.3, 15.3, -10.0);
-                            let point_light_color2 = glm::vec3(1.0, 0.0, 0.0);
-                            let point_light_color2_ambient = point_light_color2 * 0.1;
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[1].position",
-                                &point_light_pos2.as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[1].ambient",
-                                &point_light_color2_ambient.as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[1].diffuse",
-                                &point_light_color2.as_slice(),
-                            );
-                            self.shader_program.set_uniform_vec3(
-                                "point_lights[1].specular",
-                                &point_light_color2.as_slice(),
-                            );
-                            self.shader_program
-                                .set_uniform_float("point_lights[1].constant", 1.0);
-                            self.shader_program
-                                .set_uniform_float("point_lights[1].linear", 0.007);
-                            self.shader_program
-                                .set_uniform_float("point_lights[1].quadratic", 0.0002);
+            // Skinning
+            if node.skin.is_some() {
+                let joint_matrices =
+                    asset.scenes[instance.handle.scene_index].joint_matrices(instance.handle.node);
+                for (index, joint_matrix) in joint_matrices.iter().enumerate() {
+                    self.shader_program.set_uniform_matrix4x4(
+                        &format!("u_jointMatrix[{}]", index),
+                        joint_matrix.as_slice(),
+                    );
+                }
+            }
 
-                            self.shader_program
-                                .set_uniform_vec3("view_pos", &self.camera.position.as_slice());
-                            self.shader_program
-                                .set_uniform_matrix4x4("view", view.as_slice());
-                            self.shader_program
-                                .set_uniform_matrix4x4("projection", projection.as_slice());
+            self.shader_program
+                .set_uniform_int("u_attributes", primitive_info.attribute_mask());
+            asset.material(primitive_info.material_index).bind(
+                &self.shader_program,
+                &asset.textures,
+                primitive_info,
+            );
+            self.shader_program.set_uniform_matrix4x4(
+                "model",
+                (instance.model * node.global_transform()).as_slice(),
+            );
 
-                            self.solid_color_program
-                                .set_uniform_matrix4x4("view", view.as_slice());
-                            self.solid_color_program
-                                .set_uniform_matrix4x4("projection", projection.as_slice());
+            self.shader_program.activate();
+            primitive_info.draw();
+        });
 
-                            unsafe {
-                                gl::StencilFunc(gl::ALWAYS, 1, 0xFF); // all fragments should update the stencil buffer
-                                gl::StencilMask(0xFF); // enable writing to the stencil buffer
-                            }
-                            self.shader_program.activate();
-                            for row in 0..10 {
-                                for column in 0..10 {
-                                    self.shader_program.set_uniform_matrix4x4(
-                                        "model",
-                                        (glm::translate(
-                                            &glm::Mat4::identity(),
-                                            &glm::vec3(
-                                                row as f32 * -10.0,
-                                                0.0,
-                                                column as f32 * 10.0,
-                                            ),
-                                        ) * glm::scale(
-                                            &glm::Mat4::identity(),
-                                            &glm::vec3(6.0, 6.0, 6.0),
-                                        ) * global_transform)
-                                            .as_slice(),
-                                    );
+        // Outline the instances wherever they didn't write to the stencil buffer
+        unsafe {
+            gl::StencilFunc(gl::NOTEQUAL, 1, 0xFF);
+            gl::StencilMask(0x00);
+            gl::Disable(gl::DEPTH_TEST);
+        }
+        queue.render(|instance| {
+            let node = asset.node(instance.handle.scene_index, instance.handle.node);
+            self.solid_color_program.set_uniform_matrix4x4(
+                "model",
+                (instance.model * node.global_transform()).as_slice(),
+            );
 
-                                    self.shader_program.activate();
-                                    primitive_info.vao.bind();
-                                    unsafe {
-                                        gl::DrawElements(
-                                            gl::TRIANGLES,
-                                            primitive_info.num_indices,
-                                            gl::UNSIGNED_INT,
-                                            ptr::null(),
-                                        );
-                                    }
-                                }
-                            }
-                            unsafe {
-                                gl::StencilFunc(gl::NOTEQUAL, 1, 0xFF);
-                                gl::StencilMask(0x00);
-                                gl::Disable(gl::DEPTH_TEST);
-                            }
-                            self.solid_color_program.activate();
-                            self.solid_color_program.set_uniform_vec3(
-                                "highlight",
-                                glm::vec3(0.04, 0.28, 0.26).as_slice(),
-                            );
-                            for row in 0..10 {
-                                for column in 0..10 {
-                                    self.solid_color_program.set_uniform_matrix4x4(
-                                        "model",
-                                        (glm::translate(
-                                            &glm::Mat4::identity(),
-                                            &glm::vec3(
-                                                row as f32 * -10.0,
-                                                0.0,
-                                                column as f32 * 10.0,
-                                            ),
-                                        ) * glm::scale(
-                                            &glm::Mat4::identity(),
-                                            &glm::vec3(6.0, 6.0, 6.0),
-                                        ) * global_transform)
-                                            .as_slice(),
-                                    );
+            self.solid_color_program.activate();
+            asset.primitive(instance.handle).draw();
+        });
+        unsafe {
+            gl::StencilMask(0xFF);
+            gl::Enable(gl::DEPTH_TEST);
+        }
 
-                                    self.solid_color_program.activate();
-                                    primitive_info.vao.bind();
-                                    unsafe {
-                                        gl::DrawElements(
-                                            gl::TRIANGLES,
-                                            primitive_info.num_indices,
-                                            gl::UNSIGNED_INT,
-                                            ptr::null(),
-                                        );
-                                    }
-                                }
-                            }
-                            unsafe {
-                                gl::StencilMask(0xFF);
-                                gl::Enable(gl::DEPTH_TEST);
-                            }
+        // Lamps
+        let lamps = [
+            (point_light_pos1, point_light_color1),
+            (point_light_pos2, point_light_color2),
+        ];
+        for (position, color) in lamps.iter() {
+            let model = glm::translate(&glm::Mat4::identity(), position)
+                * glm::scale(&glm::Mat4::identity(), &glm::vec3(2.0, 2.0, 2.0));
+            let mut queue = RenderQueue::new();
+            asset.queue_primitives(&mut queue, &model, &view);
 
-                            // Lamp 1
-                            let lamp_mvp = projection
-                                * view
-                                * glm::translate(&glm::Mat4::identity(), &point_light_pos1.xyz())
-                                * glm::scale(&glm::Mat4::identity(), &glm::vec3(2.0, 2.0, 2.0))
-                                * global_transform;
-                            self.lamp_program.activate();
-                            self.lamp_program
-                                .set_uniform_vec3("lamp_color", &point_light_color1.as_slice());
-                            self.lamp_program
-                                .set_uniform_matrix4x4("mvp_matrix", lamp_mvp.as_slice());
-                            primitive_info.vao.bind();
-                            unsafe {
-                                gl::DrawElements(
-                                    gl::TRIANGLES,
-                                    primitive_info.num_indices,
-                                    gl::UNSIGNED_INT,
-                                    ptr::null(),
-                                );
-                            }
+            self.lamp_program
+                .set_uniform_vec3("lamp_color", color.as_slice());
+            queue.render(|instance| {
+                let node = asset.node(instance.handle.scene_index, instance.handle.node);
+                let lamp_mvp = projection * view * instance.model * node.global_transform();
+                self.lamp_program
+                    .set_uniform_matrix4x4("mvp_matrix", lamp_mvp.as_slice());
 
-                            // Lamp 2
-                            let lamp_mvp = projection
-                                * view
-                                * glm::translate(&glm::Mat4::identity(), &point_light_pos2.xyz())
-                                * glm::scale(&glm::Mat4::identity(), &glm::vec3(2.0, 2.0, 2.0))
-                                * global_transform;
-                            self.lamp_program.activate();
-                            self.lamp_program
-                                .set_uniform_vec3("lamp_color", &point_light_color2.as_slice());
-                            self.lamp_program
-                                .set_uniform_matrix4x4("mvp_matrix", lamp_mvp.as_slice());
-                            primitive_info.vao.bind();
-                            unsafe {
-                                gl::DrawElements(
-                                    gl::TRIANGLES,
-                                    primitive_info.num_indices,
-                                    gl::UNSIGNED_INT,
-                                    ptr::null(),
-                                );
-                            }
-                        }
-                    }
-                }
-            }
+                self.lamp_program.activate();
+                asset.primitive(instance.handle).draw();
+            });
         }
     }
 }
 
 fn main() {
-    let mut state = MainState::default();
-    let mut state_machine: Vec<&mut dyn State> = Vec::new();
-    state_machine.push(&mut state);
+    let mut state_machine: Vec<Box<dyn State>> = Vec::new();
+    state_machine.push(Box::new(MainState::default()));
     App::new(state_machine).run();
 }
@@ -1,8 +1,7 @@
 use sepia::app::*;
 
 fn main() {
-    let mut state: EmptyState = EmptyState;
-    let mut state_machine: Vec<&mut dyn State> = Vec::new();
-    state_machine.push(&mut state);
+    let mut state_machine: Vec<Box<dyn State>> = Vec::new();
+    state_machine.push(Box::new(EmptyState));
     App::new(state_machine).run();
 }
@@ -0,0 +1,591 @@
+use crate::gltf::*;
+use petgraph::visit::Dfs;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PlaybackMode {
+    #[default]
+    Loop,
+    PingPong,
+    Clamp,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    #[default]
+    Stopped,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum BlendMode {
+    // Interpolates from the layers below towards this layer by its weight
+    #[default]
+    Override,
+    // Adds the layer's motion relative to its first frame onto the layers below
+    Additive,
+}
+
+// Restricts a layer to a set of nodes, identified by glTF node index
+#[derive(Debug, Default, Clone)]
+pub struct BoneMask {
+    pub nodes: HashSet<usize>,
+}
+
+impl BoneMask {
+    pub fn new(nodes: &[usize]) -> Self {
+        BoneMask {
+            nodes: nodes.iter().copied().collect(),
+        }
+    }
+
+    // Masks the subtree rooted at the node with the given glTF node index
+    pub fn from_subtree(asset: &GltfAsset, root: usize) -> Self {
+        let mut nodes = HashSet::new();
+        for scene in asset.scenes.iter() {
+            for graph in scene.node_graphs.iter() {
+                let root_index = graph
+                    .node_indices()
+                    .find(|node_index| graph[*node_index].index == root);
+                if let Some(root_index) = root_index {
+                    let mut dfs = Dfs::new(graph, root_index);
+                    while let Some(node_index) = dfs.next(graph) {
+                        nodes.insert(graph[node_index].index);
+                    }
+                }
+            }
+        }
+        BoneMask { nodes }
+    }
+
+    pub fn contains(&self, node: usize) -> bool {
+        self.nodes.contains(&node)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlendLayer {
+    pub animation_index: usize,
+    pub time: f32,
+    pub weight: f32,
+    pub mode: BlendMode,
+    pub mask: Option<BoneMask>,
+}
+
+impl BlendLayer {
+    pub fn new(animation_index: usize, time: f32, weight: f32) -> Self {
+        BlendLayer {
+            animation_index,
+            time,
+            weight,
+            mode: BlendMode::Override,
+            mask: None,
+        }
+    }
+
+    fn affects(&self, node: usize) -> bool {
+        self.mask.as_ref().is_none_or(|mask| mask.contains(node))
+    }
+}
+
+// Combines animations on the same node graphs, evaluating layers from first to last
+#[derive(Debug, Default, Clone)]
+pub struct BlendStack {
+    pub layers: Vec<BlendLayer>,
+}
+
+impl BlendStack {
+    pub fn new() -> Self {
+        BlendStack::default()
+    }
+
+    pub fn push(&mut self, layer: BlendLayer) {
+        self.layers.push(layer);
+    }
+
+    pub fn evaluate(&self, asset: &mut GltfAsset) -> Pose {
+        let mut result = Pose::default();
+        for layer in self.layers.iter() {
+            if layer.weight <= 0.0 {
+                continue;
+            }
+
+            let pose = asset.sample_pose(layer.animation_index, layer.time);
+            let reference = match layer.mode {
+                BlendMode::Override => Pose::default(),
+                BlendMode::Additive => asset.sample_pose(layer.animation_index, 0.0),
+            };
+
+            for (node, transform) in pose.transforms.iter() {
+                if !layer.affects(*node) {
+                    continue;
+                }
+                let rest = asset.rest_transform(*node);
+                let blended = result.transforms.entry(*node).or_default();
+                *blended = match layer.mode {
+                    BlendMode::Override => blended.blend(transform, layer.weight, &rest),
+                    BlendMode::Additive => {
+                        let reference = reference.transforms.get(node).copied();
+                        let delta = transform.relative_to(&reference.unwrap_or_default());
+                        blended.add(&delta, layer.weight, &rest)
+                    }
+                };
+            }
+
+            for (node, weights) in pose.weights.iter() {
+                if !layer.affects(*node) {
+                    continue;
+                }
+                let reference = reference.weights.get(node);
+                let blended = result
+                    .weights
+                    .entry(*node)
+                    .or_insert_with(|| vec![0.0; weights.len()]);
+                blended.resize(weights.len(), 0.0);
+                for (index, weight) in weights.iter().enumerate() {
+                    blended[index] = match layer.mode {
+                        BlendMode::Override => {
+                            blended[index] * (1.0 - layer.weight) + weight * layer.weight
+                        }
+                        BlendMode::Additive => {
+                            let reference = reference
+                                .and_then(|reference| reference.get(index))
+                                .copied()
+                                .unwrap_or(0.0);
+                            blended[index] + (weight - reference) * layer.weight
+                        }
+                    };
+                }
+            }
+        }
+        result
+    }
+
+    pub fn apply(&self, asset: &mut GltfAsset) {
+        let pose = self.evaluate(asset);
+        asset.apply_pose(&pose);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Clip {
+    pub name: Option<String>,
+    pub duration: f32,
+    pub speed: f32,
+    pub weight: f32,
+    pub blend_mode: BlendMode,
+    pub mask: Option<BoneMask>,
+    mode: PlaybackMode,
+    state: PlaybackState,
+    // Unwrapped playback position, mapped into the clip by the playback mode
+    elapsed: f32,
+}
+
+impl Clip {
+    pub fn new(name: Option<String>, duration: f32) -> Self {
+        Clip {
+            name,
+            duration,
+            speed: 1.0,
+            weight: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.state = PlaybackState::Playing;
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == PlaybackState::Playing {
+            self.state = PlaybackState::Paused;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.state = PlaybackState::Stopped;
+        self.elapsed = 0.0;
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state == PlaybackState::Playing
+    }
+
+    pub fn mode(&self) -> PlaybackMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: PlaybackMode) {
+        // Rebase so switching modes doesn't jump to a different pose
+        self.elapsed = self.time();
+        self.mode = mode;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn set_weight(&mut self, weight: f32) {
+        self.weight = weight;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.elapsed = nalgebra::clamp(time, 0.0, self.duration);
+    }
+
+    // The current position within the clip, in seconds
+    pub fn time(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 0.0;
+        }
+        match self.mode {
+            PlaybackMode::Loop => self.elapsed.rem_euclid(self.duration),
+            PlaybackMode::Clamp => nalgebra::clamp(self.elapsed, 0.0, self.duration),
+            PlaybackMode::PingPong => {
+                let phase = self.elapsed.rem_euclid(2.0 * self.duration);
+                if phase > self.duration {
+                    2.0 * self.duration - phase
+                } else {
+                    phase
+                }
+            }
+        }
+    }
+
+    pub fn normalized_time(&self) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else {
+            self.time() / self.duration
+        }
+    }
+
+    // How far playback has advanced in clip lengths, counting completed loops
+    pub fn normalized_elapsed(&self) -> f32 {
+        if self.duration <= 0.0 {
+            0.0
+        } else {
+            self.elapsed.abs() / self.duration
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        if self.state != PlaybackState::Playing {
+            return;
+        }
+        self.elapsed += delta_time * self.speed;
+        if self.mode == PlaybackMode::Clamp {
+            self.elapsed = nalgebra::clamp(self.elapsed, 0.0, self.duration);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Fade {
+    // The clips being faded out, from the bottom layer up, stopped once the fade is done
+    from: Vec<usize>,
+    to: usize,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Fade {
+    fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            nalgebra::clamp(self.elapsed / self.duration, 0.0, 1.0)
+        }
+    }
+}
+
+// Controls playback of an asset's animations, with one clip per animation
+#[derive(Debug, Default)]
+pub struct AnimationPlayer {
+    clips: Vec<Clip>,
+    fades: Vec<Fade>,
+}
+
+impl AnimationPlayer {
+    pub fn new(asset: &GltfAsset) -> Self {
+        let clips = asset
+            .animations
+            .iter()
+            .map(|animation| Clip::new(animation.name.clone(), animation.duration))
+            .collect::<Vec<_>>();
+        AnimationPlayer {
+            clips,
+            fades: Vec::new(),
+        }
+    }
+
+    pub fn clips(&self) -> &[Clip] {
+        &self.clips
+    }
+
+    pub fn clip_index(&self, name: &str) -> Option<usize> {
+        self.clips
+            .iter()
+            .position(|clip| clip.name.as_deref() == Some(name))
+    }
+
+    pub fn clip(&self, index: usize) -> Option<&Clip> {
+        self.clips.get(index)
+    }
+
+    pub fn clip_mut(&mut self, index: usize) -> Option<&mut Clip> {
+        self.clips.get_mut(index)
+    }
+
+    pub fn clip_by_name(&self, name: &str) -> Option<&Clip> {
+        self.clip_index(name).map(|index| &self.clips[index])
+    }
+
+    pub fn clip_by_name_mut(&mut self, name: &str) -> Option<&mut Clip> {
+        match self.clip_index(name) {
+            Some(index) => Some(&mut self.clips[index]),
+            None => None,
+        }
+    }
+
+    // Starts playing a clip and fades it in over the other, stopping the other once done
+    // Clips that an interrupted fade was still fading out are faded out along with it
+    pub fn cross_fade(&mut self, from: usize, to: usize, duration: f32) {
+        let mut faded_out = Vec::new();
+        for fade in self.fades.iter() {
+            if fade.to == to || fade.to == from {
+                faded_out.extend(fade.from.iter().copied());
+            }
+        }
+        faded_out.push(from);
+        let mut seen = HashSet::new();
+        faded_out.retain(|clip| *clip != to && seen.insert(*clip));
+
+        self.fades.retain(|fade| fade.to != to && fade.to != from);
+        for fade in self.fades.iter_mut() {
+            fade.from.retain(|clip| *clip != to);
+        }
+
+        self.clips[to].weight = 0.0;
+        self.clips[to].play();
+        self.fades.push(Fade {
+            from: faded_out,
+            to,
+            duration,
+            elapsed: 0.0,
+        });
+        self.update_fades(0.0);
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        for clip in self.clips.iter_mut() {
+            clip.update(delta_time);
+        }
+        self.update_fades(delta_time);
+    }
+
+    fn update_fades(&mut self, delta_time: f32) {
+        for fade in self.fades.iter_mut() {
+            fade.elapsed += delta_time;
+            let progress = fade.progress();
+            self.clips[fade.to].weight = progress;
+            if progress >= 1.0 {
+                for from in fade.from.iter() {
+                    self.clips[*from].stop();
+                }
+            }
+        }
+        self.fades.retain(|fade| fade.progress() < 1.0);
+    }
+
+    // Override clips are layered below clips that are fading in, with additive clips on top
+    // Clips being faded out keep the order they were layered in
+    pub fn blend_stack(&self) -> BlendStack {
+        let fading_in = |index: usize| self.fades.iter().any(|fade| fade.to == index);
+        let fading_out = |index: usize| {
+            self.fades
+                .iter()
+                .find_map(|fade| fade.from.iter().position(|from| *from == index))
+                .unwrap_or(0)
+        };
+        let mut layers = self
+            .clips
+            .iter()
+            .enumerate()
+            .filter(|(_, clip)| clip.state() != PlaybackState::Stopped)
+            .map(|(index, clip)| BlendLayer {
+                animation_index: index,
+                time: clip.time(),
+                weight: clip.weight,
+                mode: clip.blend_mode,
+                mask: clip.mask.clone(),
+            })
+            .collect::<Vec<_>>();
+        layers.sort_by_key(|layer| {
+            (
+                layer.mode == BlendMode::Additive,
+                fading_in(layer.animation_index),
+                fading_out(layer.animation_index),
+            )
+        });
+        BlendStack { layers }
+    }
+
+    // Poses the asset with every clip that is playing or paused
+    pub fn apply(&self, asset: &mut GltfAsset) {
+        self.blend_stack().apply(asset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(names: &[&str]) -> AnimationPlayer {
+        AnimationPlayer {
+            clips: names
+                .iter()
+                .map(|name| Clip::new(Some(name.to_string()), 1.0))
+                .collect(),
+            fades: Vec::new(),
+        }
+    }
+
+    fn layer_order(player: &AnimationPlayer) -> Vec<usize> {
+        player
+            .blend_stack()
+            .layers
+            .iter()
+            .map(|layer| layer.animation_index)
+            .collect()
+    }
+
+    #[test]
+    fn cross_fade_stops_the_faded_out_clip() {
+        let mut player = player(&["Idle", "Walk"]);
+        player.clips[0].play();
+        player.cross_fade(0, 1, 1.0);
+        assert_eq!(layer_order(&player), vec![0, 1]);
+
+        player.update(0.5);
+        assert!((player.clips[1].weight - 0.5).abs() < 1e-6);
+        player.update(0.5);
+        assert_eq!(player.clips[0].state(), PlaybackState::Stopped);
+        assert_eq!(layer_order(&player), vec![1]);
+    }
+
+    #[test]
+    fn interrupted_fades_fade_out_every_clip() {
+        let mut player = player(&["Idle", "Walk", "Run"]);
+        player.clips[0].play();
+        player.cross_fade(0, 1, 1.0);
+        player.update(0.5);
+
+        // Walk is interrupted halfway, and stays layered over Idle while Run fades in
+        player.cross_fade(1, 2, 1.0);
+        assert_eq!(layer_order(&player), vec![0, 1, 2]);
+        assert!((player.clips[1].weight - 0.5).abs() < 1e-6);
+        assert_eq!(player.clips[2].weight, 0.0);
+
+        player.update(1.0);
+        assert_eq!(player.clips[0].state(), PlaybackState::Stopped);
+        assert_eq!(player.clips[1].state(), PlaybackState::Stopped);
+        assert_eq!(player.clips[2].weight, 1.0);
+        assert_eq!(layer_order(&player), vec![2]);
+    }
+
+    #[test]
+    fn reversed_fades_keep_the_clip_fading_back_in() {
+        let mut player = player(&["Idle", "Walk"]);
+        player.clips[0].play();
+        player.cross_fade(0, 1, 1.0);
+        player.update(0.5);
+
+        player.cross_fade(1, 0, 1.0);
+        player.update(1.0);
+        assert_eq!(player.clips[0].state(), PlaybackState::Playing);
+        assert_eq!(player.clips[0].weight, 1.0);
+        assert_eq!(player.clips[1].state(), PlaybackState::Stopped);
+    }
+
+    #[test]
+    fn bone_masks_filter_the_nodes_a_layer_poses() {
+        // Animations 6 and 7 of InterpolationTest.glb move nodes 8 and 9
+        let mut asset = GltfAsset::import("assets/models/InterpolationTest.glb");
+        let mask = BoneMask::new(&[9]);
+        let mut stack = BlendStack::new();
+        for animation_index in &[6, 7] {
+            let mut layer = BlendLayer::new(*animation_index, 0.5, 1.0);
+            layer.mask = Some(mask.clone());
+            stack.push(layer);
+        }
+
+        let pose = stack.evaluate(&mut asset);
+        assert!(!pose.transforms.contains_key(&8));
+        assert!(pose.transforms.contains_key(&9));
+    }
+
+    #[test]
+    fn subtree_masks_include_every_descendant() {
+        let asset = GltfAsset::import("assets/models/RiggedSimple.glb");
+        let mask = BoneMask::from_subtree(&asset, 2);
+        assert_eq!(mask.nodes, [2, 3].iter().copied().collect());
+        let mask = BoneMask::from_subtree(&asset, 0);
+        assert_eq!(mask.nodes, [0, 1, 2, 3, 4].iter().copied().collect());
+    }
+
+    #[test]
+    fn nodes_only_the_stopped_clip_animates_return_to_rest() {
+        // Step Translation moves Cube.006, CubicSpline Translation moves Cube.008
+        let mut asset = GltfAsset::import("assets/models/InterpolationTest.glb");
+        let mut player = AnimationPlayer::new(&asset);
+        let step = asset.find_animation("Step Translation").unwrap();
+        let cubic = asset.find_animation("CubicSpline Translation").unwrap();
+        let (step_scene, step_node) = asset.find_node("Cube.006").unwrap();
+        let (cubic_scene, cubic_node) = asset.find_node("Cube.008").unwrap();
+
+        player.clips[step].play();
+        player.update(0.5);
+        player.apply(&mut asset);
+        assert!(!asset
+            .node(step_scene, step_node)
+            .animation_transform
+            .is_empty());
+
+        player.cross_fade(step, cubic, 0.25);
+        player.update(0.5);
+        player.apply(&mut asset);
+        let node = asset.node(step_scene, step_node);
+        assert!(node.animation_transform.is_empty());
+        assert_eq!(node.local_matrix(), node.local_transform);
+        assert!(!asset
+            .node(cubic_scene, cubic_node)
+            .animation_transform
+            .is_empty());
+    }
+
+    #[test]
+    fn sampling_one_animation_resets_the_others() {
+        let mut asset = GltfAsset::import("assets/models/InterpolationTest.glb");
+        let step = asset.find_animation("Step Translation").unwrap();
+        let cubic = asset.find_animation("CubicSpline Translation").unwrap();
+        let (scene_index, handle) = asset.find_node("Cube.006").unwrap();
+
+        asset.sample_animation(step, 0.5);
+        assert!(!asset
+            .node(scene_index, handle)
+            .animation_transform
+            .is_empty());
+        asset.sample_animation(cubic, 0.5);
+        assert!(asset
+            .node(scene_index, handle)
+            .animation_transform
+            .is_empty());
+    }
+}
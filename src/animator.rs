@@ -0,0 +1,460 @@
+use crate::animation::*;
+use crate::gltf::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, fmt, fs};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimatorError {
+    Io(String),
+    Parse(String),
+    // A state plays an animation the asset doesn't have
+    UnknownClip(String),
+    // A transition or the default state names a state that doesn't exist
+    UnknownState(String),
+}
+
+impl fmt::Display for AnimatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimatorError::Io(error) => write!(f, "Couldn't read animator file: {}", error),
+            AnimatorError::Parse(error) => {
+                write!(f, "Couldn't parse animator description: {}", error)
+            }
+            AnimatorError::UnknownClip(name) => write!(f, "Couldn't find animation '{}'", name),
+            AnimatorError::UnknownState(name) => {
+                write!(f, "Couldn't find animator state '{}'", name)
+            }
+        }
+    }
+}
+
+impl Error for AnimatorError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Parameter {
+    Bool(bool),
+    Float(f32),
+    // Stays set until a transition that checks it is taken
+    Trigger(bool),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Condition {
+    If(String),
+    IfNot(String),
+    Greater(String, f32),
+    Less(String, f32),
+    Trigger(String),
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDescription {
+    pub name: String,
+    // The name of the animation the state plays
+    pub clip: String,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub mode: PlaybackMode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransitionDescription {
+    // Transitions without a source state can be taken from any other state
+    #[serde(default)]
+    pub from: Option<String>,
+    pub to: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    // The cross-fade duration in seconds
+    #[serde(default)]
+    pub duration: f32,
+    // The normalized time the source state must reach before transitioning,
+    // values above 1 wait for more than one loop
+    #[serde(default)]
+    pub exit_time: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnimatorDescription {
+    #[serde(default)]
+    pub parameters: HashMap<String, Parameter>,
+    pub default_state: String,
+    pub states: Vec<StateDescription>,
+    #[serde(default)]
+    pub transitions: Vec<TransitionDescription>,
+}
+
+impl AnimatorDescription {
+    pub fn from_file(path: &str) -> Result<Self, AnimatorError> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| AnimatorError::Io(format!("{}: {}", path, error)))?;
+        AnimatorDescription::from_json(&text)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, AnimatorError> {
+        serde_json::from_str(json).map_err(|error| AnimatorError::Parse(error.to_string()))
+    }
+}
+
+#[derive(Debug)]
+struct AnimatorState {
+    name: String,
+    clip_index: usize,
+    speed: f32,
+    mode: PlaybackMode,
+}
+
+#[derive(Debug)]
+struct AnimatorTransition {
+    from: Option<usize>,
+    to: usize,
+    conditions: Vec<Condition>,
+    duration: f32,
+    exit_time: Option<f32>,
+}
+
+// Drives an AnimationPlayer with a state machine of named states bound to clips
+#[derive(Debug)]
+pub struct Animator {
+    states: Vec<AnimatorState>,
+    transitions: Vec<AnimatorTransition>,
+    parameters: HashMap<String, Parameter>,
+    player: AnimationPlayer,
+    current_state: usize,
+}
+
+impl Animator {
+    pub fn new(
+        description: &AnimatorDescription,
+        asset: &GltfAsset,
+    ) -> Result<Self, AnimatorError> {
+        Animator::with_player(description, AnimationPlayer::new(asset))
+    }
+
+    // Wires the state machine to a player whose clips may already be configured
+    pub fn with_player(
+        description: &AnimatorDescription,
+        player: AnimationPlayer,
+    ) -> Result<Self, AnimatorError> {
+        let states = description
+            .states
+            .iter()
+            .map(|state| {
+                let clip_index = player
+                    .clip_index(&state.clip)
+                    .ok_or_else(|| AnimatorError::UnknownClip(state.clip.clone()))?;
+                Ok(AnimatorState {
+                    name: state.name.clone(),
+                    clip_index,
+                    speed: state.speed,
+                    mode: state.mode,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let state_index = |name: &str| {
+            states
+                .iter()
+                .position(|state| state.name == name)
+                .ok_or_else(|| AnimatorError::UnknownState(name.to_string()))
+        };
+
+        let transitions = description
+            .transitions
+            .iter()
+            .map(|transition| {
+                Ok(AnimatorTransition {
+                    from: match &transition.from {
+                        Some(from) => Some(state_index(from)?),
+                        None => None,
+                    },
+                    to: state_index(&transition.to)?,
+                    conditions: transition.conditions.clone(),
+                    duration: transition.duration,
+                    exit_time: transition.exit_time,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let current_state = state_index(&description.default_state)?;
+
+        let mut animator = Animator {
+            states,
+            transitions,
+            parameters: description.parameters.clone(),
+            player,
+            current_state,
+        };
+        animator.enter_state(current_state, 0.0);
+        Ok(animator)
+    }
+
+    pub fn from_file(path: &str, asset: &GltfAsset) -> Result<Self, AnimatorError> {
+        Animator::new(&AnimatorDescription::from_file(path)?, asset)
+    }
+
+    pub fn current_state(&self) -> &str {
+        &self.states[self.current_state].name
+    }
+
+    pub fn player(&self) -> &AnimationPlayer {
+        &self.player
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<Parameter> {
+        self.parameters.get(name).copied()
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) {
+        self.parameters
+            .insert(name.to_string(), Parameter::Bool(value));
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.parameters
+            .insert(name.to_string(), Parameter::Float(value));
+    }
+
+    pub fn set_trigger(&mut self, name: &str) {
+        self.parameters
+            .insert(name.to_string(), Parameter::Trigger(true));
+    }
+
+    pub fn reset_trigger(&mut self, name: &str) {
+        self.parameters
+            .insert(name.to_string(), Parameter::Trigger(false));
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.player.update(delta_time);
+
+        if let Some(transition_index) = self.find_transition() {
+            let transition = &self.transitions[transition_index];
+            for condition in transition.conditions.iter() {
+                if let Condition::Trigger(name) = condition {
+                    self.parameters
+                        .insert(name.to_string(), Parameter::Trigger(false));
+                }
+            }
+            let (to, duration) = (transition.to, transition.duration);
+            self.enter_state(to, duration);
+        }
+    }
+
+    pub fn apply(&self, asset: &mut GltfAsset) {
+        self.player.apply(asset);
+    }
+
+    fn find_transition(&self) -> Option<usize> {
+        let clip_index = self.states[self.current_state].clip_index;
+        // Looping clips wrap their normalized time, so exit times count completed loops
+        let normalized_elapsed = self
+            .player
+            .clip(clip_index)
+            .map_or(0.0, |clip| clip.normalized_elapsed());
+
+        self.transitions.iter().position(|transition| {
+            let from_current_state = match transition.from {
+                Some(from) => from == self.current_state,
+                None => transition.to != self.current_state,
+            };
+            let exited = transition
+                .exit_time
+                .is_none_or(|exit_time| normalized_elapsed >= exit_time);
+            from_current_state
+                && exited
+                && transition
+                    .conditions
+                    .iter()
+                    .all(|condition| self.is_met(condition))
+        })
+    }
+
+    fn is_met(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::If(name) => self.parameter(name) == Some(Parameter::Bool(true)),
+            Condition::IfNot(name) => self.parameter(name) == Some(Parameter::Bool(false)),
+            Condition::Greater(name, threshold) => match self.parameter(name) {
+                Some(Parameter::Float(value)) => value > *threshold,
+                _ => false,
+            },
+            Condition::Less(name, threshold) => match self.parameter(name) {
+                Some(Parameter::Float(value)) => value < *threshold,
+                _ => false,
+            },
+            Condition::Trigger(name) => self.parameter(name) == Some(Parameter::Trigger(true)),
+        }
+    }
+
+    fn enter_state(&mut self, state_index: usize, duration: f32) {
+        let previous_clip_index = self.states[self.current_state].clip_index;
+        let state = &self.states[state_index];
+
+        let clip = self
+            .player
+            .clip_mut(state.clip_index)
+            .expect("Couldn't get animation clip!");
+        clip.set_mode(state.mode);
+        clip.set_speed(state.speed);
+        clip.seek(0.0);
+
+        // Re-entering the playing clip restarts it instead of fading it against itself
+        if previous_clip_index == state.clip_index {
+            clip.set_weight(1.0);
+            clip.play();
+        } else {
+            self.player
+                .cross_fade(previous_clip_index, state.clip_index, duration);
+        }
+
+        self.current_state = state_index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wolf() -> GltfAsset {
+        GltfAsset::import("assets/models/Wolf.glb")
+    }
+
+    fn animator(asset: &GltfAsset) -> Animator {
+        Animator::from_file("assets/animators/wolf.json", asset).unwrap()
+    }
+
+    #[test]
+    fn float_parameters_drive_transitions() {
+        let asset = wolf();
+        let mut animator = animator(&asset);
+        assert_eq!(animator.current_state(), "Idle");
+
+        animator.set_float("speed", 0.5);
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Walk");
+        animator.set_float("speed", 0.8);
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Run");
+        animator.set_float("speed", 0.0);
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Walk");
+    }
+
+    #[test]
+    fn bool_parameters_drive_transitions() {
+        let asset = wolf();
+        let mut animator = animator(&asset);
+        animator.set_bool("sitting", true);
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Sit");
+
+        // Sit only returns to Idle once the clip has finished
+        animator.set_bool("sitting", false);
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Sit");
+        let duration = animator
+            .player()
+            .clip_by_name("Wolf_Skeleton|Wolf_seat_")
+            .unwrap()
+            .duration;
+        animator.update(duration);
+        assert_eq!(animator.current_state(), "Idle");
+    }
+
+    #[test]
+    fn exit_times_count_completed_loops() {
+        let description = AnimatorDescription::from_json(
+            r#"{
+                "default_state": "Walk",
+                "states": [
+                    { "name": "Walk", "clip": "Wolf_Skeleton|Wolf_Walk_cycle_" },
+                    { "name": "Idle", "clip": "Wolf_Skeleton|Wolf_Idle_" }
+                ],
+                "transitions": [{ "from": "Walk", "to": "Idle", "exit_time": 1.0 }]
+            }"#,
+        )
+        .unwrap();
+        let asset = wolf();
+        let mut animator = Animator::new(&description, &asset).unwrap();
+        let duration = animator
+            .player()
+            .clip_by_name("Wolf_Skeleton|Wolf_Walk_cycle_")
+            .unwrap()
+            .duration;
+
+        animator.update(duration * 0.5);
+        assert_eq!(animator.current_state(), "Walk");
+        animator.update(duration * 0.5);
+        assert_eq!(animator.current_state(), "Idle");
+    }
+
+    #[test]
+    fn triggers_are_consumed_by_their_transition() {
+        let description = AnimatorDescription::from_json(
+            r#"{
+                "parameters": { "jump": { "Trigger": false } },
+                "default_state": "Idle",
+                "states": [
+                    { "name": "Idle", "clip": "Wolf_Skeleton|Wolf_Idle_" },
+                    { "name": "Run", "clip": "Wolf_Skeleton|Wolf_Run_Cycle_" }
+                ],
+                "transitions": [
+                    { "to": "Run", "conditions": [{ "Trigger": "jump" }] },
+                    { "from": "Run", "to": "Idle" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let asset = wolf();
+        let mut animator = Animator::new(&description, &asset).unwrap();
+
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Idle");
+        animator.set_trigger("jump");
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Run");
+        assert_eq!(animator.parameter("jump"), Some(Parameter::Trigger(false)));
+
+        // Without the trigger set again, the animator doesn't go back to Run
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Idle");
+        animator.update(0.0);
+        assert_eq!(animator.current_state(), "Idle");
+    }
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let asset = wolf();
+        let description = AnimatorDescription::from_json(
+            r#"{ "default_state": "Idle", "states": [{ "name": "Idle", "clip": "Missing" }] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Animator::new(&description, &asset).unwrap_err(),
+            AnimatorError::UnknownClip("Missing".to_string())
+        );
+
+        let description = AnimatorDescription::from_json(
+            r#"{
+                "default_state": "Idle",
+                "states": [{ "name": "Idle", "clip": "Wolf_Skeleton|Wolf_Idle_" }],
+                "transitions": [{ "from": "Idle", "to": "Fly" }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Animator::new(&description, &asset).unwrap_err(),
+            AnimatorError::UnknownState("Fly".to_string())
+        );
+
+        assert!(matches!(
+            AnimatorDescription::from_json("{"),
+            Err(AnimatorError::Parse(_))
+        ));
+    }
+}
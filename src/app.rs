@@ -1,3 +1,4 @@
+use crate::resource;
 pub use gl::types::*;
 pub use glfw::{Action, Context, CursorMode, Key, WindowEvent};
 use std::{cmp, sync::mpsc::Receiver};
@@ -25,19 +26,33 @@ pub trait State {
     fn render(&mut self, _: &mut StateData) {}
 }
 
+// When enabled, linear shader output is encoded as sRGB when written to the window
+// Textures holding color data should then be loaded with the sRGB color space
+pub fn set_gamma_correction(enabled: bool) {
+    unsafe {
+        if enabled {
+            gl::Enable(gl::FRAMEBUFFER_SRGB);
+        } else {
+            gl::Disable(gl::FRAMEBUFFER_SRGB);
+        }
+    }
+}
+
 pub struct EmptyState;
 impl State for EmptyState {}
 
-pub struct App<'a> {
+// The app owns its states, so that the GL objects they hold are dropped while the context is current
+pub struct App {
     context: glfw::Glfw,
     window: glfw::Window,
     events: Receiver<(f64, glfw::WindowEvent)>,
-    state_machine: Vec<&'a mut dyn State>,
+    state_machine: Vec<Box<dyn State>>,
 }
 
-impl<'a> App<'a> {
-    pub fn new(state_machine: Vec<&'a mut dyn State>) -> Self {
-        let context = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+impl App {
+    pub fn new(state_machine: Vec<Box<dyn State>>) -> Self {
+        let mut context = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
+        context.window_hint(glfw::WindowHint::SRgbCapable(true));
         let (mut window, events) = context
             .create_window(1920, 1080, "Sepia", glfw::WindowMode::Windowed)
             .expect("Failed to create GLFW window.");
@@ -105,6 +120,18 @@ impl<'a> App<'a> {
             state.render(&mut state_data);
 
             self.window.swap_buffers();
+
+            // Objects dropped during the frame are deleted here, on the thread that owns the context
+            resource::delete_pending();
         }
     }
 }
+
+// Drops the states, then deletes everything released since the last frame
+// while the context is still current
+impl Drop for App {
+    fn drop(&mut self) {
+        self.state_machine.clear();
+        resource::delete_pending();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::vao::*;
+use crate::{resource::*, vao::*};
 use gl::types::*;
 use std::{mem, slice};
 
@@ -14,6 +14,7 @@ pub enum DrawingHint {
     DynamicCopy,
 }
 
+#[derive(Debug)]
 pub enum BufferKind {
     Array,
     Element,
@@ -25,7 +26,8 @@ impl Default for BufferKind {
     }
 }
 
-#[derive(Default)]
+// A default buffer has id 0, which names no GL object and is never tracked or released
+#[derive(Default, Debug)]
 pub struct Buffer {
     id: GLuint,
     kind: BufferKind,
@@ -38,10 +40,11 @@ impl Buffer {
         unsafe {
             gl::GenBuffers(1, &mut id);
         }
+        track(ResourceKind::Buffer, id);
         Buffer {
             id,
             kind,
-            ..Default::default()
+            data: Vec::new(),
         }
     }
 
@@ -65,15 +68,24 @@ impl Buffer {
         self.data.clear();
     }
 
-    pub fn bind(&self) {
+    // Overwrites the start of the buffer's storage instead of reallocating it
+    pub fn update(&mut self, vao: &VertexArrayObject) {
+        vao.bind();
+        self.bind();
         unsafe {
-            gl::BindBuffer(self.kind(), self.id as u32);
+            gl::BufferSubData(
+                self.kind(),
+                0,
+                (self.data.len() * mem::size_of::<u8>()) as GLsizeiptr,
+                self.data.as_ptr() as *const GLvoid,
+            );
         }
+        self.data.clear();
     }
 
-    pub fn free(&self) {
+    pub fn bind(&self) {
         unsafe {
-            gl::DeleteBuffers(1, &self.id as *const u32);
+            gl::BindBuffer(self.kind(), self.id as u32);
         }
     }
 
@@ -124,3 +136,9 @@ impl Buffer {
         }
     }
 }
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        release(ResourceKind::Buffer, self.id);
+    }
+}
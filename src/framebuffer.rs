@@ -1,5 +1,6 @@
-use crate::texture::*;
+use crate::{resource::*, texture::*};
 
+// The ids stay 0 until create_with_texture makes the objects, and 0 is never released
 #[derive(Default)]
 pub struct Framebuffer {
     width: u32,
@@ -20,6 +21,8 @@ impl Framebuffer {
         self.color_texture = Texture::new(gl::TEXTURE_2D);
         self.color_texture
             .load_data(width, height, &[] as &[u8], gl::RGB, gl::TEXTURE_2D);
+        // Recreating the framebuffer replaces the previous one
+        release(ResourceKind::Framebuffer, self.id);
         unsafe {
             gl::GenFramebuffers(1, &mut self.id);
             gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
@@ -31,9 +34,11 @@ impl Framebuffer {
                 0,
             );
         }
+        track(ResourceKind::Framebuffer, self.id);
     }
 
     pub fn add_depth_buffer(&mut self) {
+        release(ResourceKind::Renderbuffer, self.rbo_id);
         unsafe {
             gl::GenRenderbuffers(1, &mut self.rbo_id);
             gl::BindRenderbuffer(gl::RENDERBUFFER, self.rbo_id);
@@ -51,6 +56,7 @@ impl Framebuffer {
                 self.rbo_id,
             );
         }
+        track(ResourceKind::Renderbuffer, self.rbo_id);
     }
 
     pub fn bind(&self) {
@@ -69,3 +75,10 @@ impl Framebuffer {
         }
     }
 }
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        release(ResourceKind::Renderbuffer, self.rbo_id);
+        release(ResourceKind::Framebuffer, self.id);
+    }
+}
@@ -1,10 +1,21 @@
 use crate::buffer::*;
+use crate::material::*;
+use crate::meshprocessing::*;
+use crate::renderqueue::*;
+use crate::texture::*;
 use crate::vao::*;
 pub use gl::types::*;
 pub use gl::types::*;
 use gltf::{
-    animation::{util::ReadOutputs, Interpolation},
+    animation::{
+        util::{MorphTargetWeights, ReadOutputs, Rotations},
+        Interpolation,
+    },
     image::Format,
+    mesh::{
+        util::{ReadColors, ReadTexCoords, ReadWeights},
+        Mode,
+    },
 };
 use nalgebra::{Matrix4, Quaternion, UnitQuaternion};
 use nalgebra_glm as glm;
@@ -13,6 +24,18 @@ use petgraph::{
     prelude::*,
     visit::Dfs,
 };
+use serde_json::Value;
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    mem,
+    ops::{Add, Mul},
+    path::Path,
+    ptr, slice,
+    sync::Arc,
+};
 
 // TODO: Load bounding volumes using ncollide
 
@@ -23,100 +46,173 @@ enum TransformationSet {
     Translations(Vec<glm::Vec3>),
     Rotations(Vec<glm::Vec4>),
     Scales(Vec<glm::Vec3>),
-    MorphTargetWeights(Vec<f32>),
+    // One track of weights per morph target
+    MorphTargetWeights(Vec<Vec<f32>>),
+}
+
+fn as_bytes<T>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) }
 }
 
 #[repr(C)]
-#[derive(Default)]
+#[derive(Default, Debug, Clone, Copy)]
 pub struct Vertex {
     position: Option<glm::Vec3>,
     normal: Option<glm::Vec3>,
     tex_coords_0: Option<glm::Vec2>,
     tex_coords_1: Option<glm::Vec2>,
-    joints_0: Option<glm::Vec4>,
+    joints_0: Option<glm::TVec4<u16>>,
     weights_0: Option<glm::Vec4>,
+    tangent: Option<glm::Vec4>,
+    color_0: Option<glm::Vec4>,
 }
 
 impl Vertex {
-    // Packs the vertex data in a specific order
-    pub fn pack_data(&self) -> Vec<f32> {
-        let mut data: Vec<f32> = Vec::new();
-
-        if let Some(position) = self.position {
-            data.extend(&position.as_slice().to_vec());
+    // The attribute's data as the bytes uploaded for it
+    pub fn attribute(&self, semantic: Semantic) -> Option<&[u8]> {
+        match semantic {
+            Semantic::Position => self.position.as_ref().map(|data| as_bytes(data.as_slice())),
+            Semantic::Normal => self.normal.as_ref().map(|data| as_bytes(data.as_slice())),
+            Semantic::TexCoords0 => self
+                .tex_coords_0
+                .as_ref()
+                .map(|data| as_bytes(data.as_slice())),
+            Semantic::Joints0 => self.joints_0.as_ref().map(|data| as_bytes(data.as_slice())),
+            Semantic::Weights0 => self
+                .weights_0
+                .as_ref()
+                .map(|data| as_bytes(data.as_slice())),
+            Semantic::Tangent => self.tangent.as_ref().map(|data| as_bytes(data.as_slice())),
+            Semantic::TexCoords1 => self
+                .tex_coords_1
+                .as_ref()
+                .map(|data| as_bytes(data.as_slice())),
+            Semantic::Color0 => self.color_0.as_ref().map(|data| as_bytes(data.as_slice())),
+            Semantic::Custom(_) => None,
         }
+    }
 
-        if let Some(normal) = self.normal {
-            data.extend(&normal.as_slice().to_vec());
+    // The attributes that are present, in location order, which is also the order they are packed in
+    // Joint indices are uploaded as integers, everything else as floats
+    pub fn layout(&self) -> VertexLayout {
+        let mut layout = VertexLayout::new();
+        if self.position.is_some() {
+            layout.float(Semantic::Position, 3);
         }
-
-        if let Some(tex_coords_0) = self.tex_coords_0 {
-            data.extend(&tex_coords_0.as_slice().to_vec());
+        if self.normal.is_some() {
+            layout.float(Semantic::Normal, 3);
         }
-
-        if let Some(joints_0) = self.joints_0 {
-            data.extend(&joints_0.as_slice().to_vec());
+        if self.tex_coords_0.is_some() {
+            layout.float(Semantic::TexCoords0, 2);
         }
-
-        if let Some(weights_0) = self.weights_0 {
-            data.extend(&weights_0.as_slice().to_vec());
+        if self.joints_0.is_some() {
+            layout.integer(Semantic::Joints0, ComponentType::UnsignedShort, 4);
         }
-
-        data
+        if self.weights_0.is_some() {
+            layout.float(Semantic::Weights0, 4);
+        }
+        if self.tangent.is_some() {
+            layout.float(Semantic::Tangent, 4);
+        }
+        if self.tex_coords_1.is_some() {
+            layout.float(Semantic::TexCoords1, 2);
+        }
+        if self.color_0.is_some() {
+            layout.float(Semantic::Color0, 4);
+        }
+        layout
     }
 }
 
+#[derive(Debug)]
 pub struct VertexSet {
     pub vertices: Vec<Vertex>,
 }
 
 impl VertexSet {
-    fn pack_vertices(&self) -> Vec<f32> {
-        self.vertices
-            .iter()
-            .map(|vertex| vertex.pack_data())
-            .flatten()
-            .collect::<Vec<_>>()
-    }
-
-    // This determines the order that attributes are configured in
-    // This must patch the packing order for vertices
-    fn data_lengths(&self) -> Vec<u32> {
-        let vec2_length = 2;
-        let vec3_length = 3;
-        let vec4_length = 4;
-
-        let mut data_lengths: Vec<u32> = Vec::new();
-
-        if self.vertices.is_empty() {
-            return data_lengths;
+    // Blends the weighted morph target displacements into a copy of the vertices
+    pub fn morphed(&self, morph_targets: &[MorphTarget], weights: &[f32]) -> VertexSet {
+        let mut vertices = self.vertices.clone();
+        for (morph_target, weight) in morph_targets.iter().zip(weights.iter()) {
+            if *weight == 0.0 {
+                continue;
+            }
+            for (index, vertex) in vertices.iter_mut().enumerate() {
+                if let (Some(position), Some(displacement)) =
+                    (vertex.position.as_mut(), morph_target.positions.get(index))
+                {
+                    *position += displacement * *weight;
+                }
+                if let (Some(normal), Some(displacement)) =
+                    (vertex.normal.as_mut(), morph_target.normals.get(index))
+                {
+                    *normal += displacement * *weight;
+                }
+                // Tangent displacements don't affect the handedness in w
+                if let (Some(tangent), Some(displacement)) =
+                    (vertex.tangent.as_mut(), morph_target.tangents.get(index))
+                {
+                    let displacement = displacement * *weight;
+                    tangent.x += displacement.x;
+                    tangent.y += displacement.y;
+                    tangent.z += displacement.z;
+                }
+            }
         }
+        VertexSet { vertices }
+    }
 
-        // first vertex is representative of all other vertices
-        let first_vertex = &self.vertices[0];
-
-        if first_vertex.position.is_some() {
-            data_lengths.push(vec3_length);
+    fn pack_vertices(&self) -> Vec<u8> {
+        let layout = self.layout();
+        let mut data = Vec::with_capacity(layout.stride() as usize * self.vertices.len());
+        for vertex in self.vertices.iter() {
+            for attribute in layout.attributes() {
+                data.extend(
+                    vertex
+                        .attribute(attribute.semantic)
+                        .expect("Couldn't get vertex attribute!"),
+                );
+            }
         }
+        data
+    }
 
-        if first_vertex.normal.is_some() {
-            data_lengths.push(vec3_length);
-        }
+    // The first vertex is representative of all other vertices
+    pub fn layout(&self) -> VertexLayout {
+        self.vertices
+            .first()
+            .map_or_else(VertexLayout::new, |vertex| vertex.layout())
+    }
 
-        if first_vertex.tex_coords_0.is_some() {
-            data_lengths.push(vec2_length);
-        }
+    pub fn attributes(&self) -> Vec<Semantic> {
+        self.layout()
+            .attributes()
+            .iter()
+            .map(|attribute| attribute.semantic)
+            .collect::<Vec<_>>()
+    }
+}
 
-        if first_vertex.joints_0.is_some() {
-            data_lengths.push(vec4_length);
-        }
+// Locates a node within one of a scene's node graphs
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NodeHandle {
+    pub graph_index: usize,
+    pub node_index: NodeIndex,
+}
 
-        if first_vertex.weights_0.is_some() {
-            data_lengths.push(vec4_length);
-        }
+// Locates one primitive of a node's mesh within an asset
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PrimitiveHandle {
+    pub scene_index: usize,
+    pub node: NodeHandle,
+    pub primitive_index: usize,
+}
 
-        data_lengths
-    }
+// One draw of a primitive, with a model matrix applied on top of its node's global transform
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PrimitiveInstance {
+    pub handle: PrimitiveHandle,
+    pub model: glm::Mat4,
 }
 
 #[derive(Debug)]
@@ -124,20 +220,64 @@ pub struct Skin {
     pub joints: Vec<Joint>,
 }
 
+impl Skin {
+    // Computes the joint matrix palette for a mesh node with the given global transform
+    pub fn joint_matrices(
+        &self,
+        mesh_global_transform: &glm::Mat4,
+        node_graphs: &[NodeGraph],
+    ) -> Vec<glm::Mat4> {
+        let inverse_mesh_transform = glm::inverse(mesh_global_transform);
+        self.joints
+            .iter()
+            .map(|joint| match joint.handle {
+                Some(handle) => {
+                    let joint_global_transform =
+                        node_graphs[handle.graph_index][handle.node_index].global_transform();
+                    inverse_mesh_transform * joint_global_transform * joint.inverse_bind_matrix
+                }
+                None => glm::Mat4::identity(),
+            })
+            .collect::<Vec<_>>()
+    }
+}
+
 #[derive(Debug)]
 pub struct Joint {
+    // The glTF node index of the joint
     pub index: usize,
+    // Resolved once all of the scene's graphs are loaded
+    pub handle: Option<NodeHandle>,
     pub inverse_bind_matrix: glm::Mat4,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Copy, Clone)]
 pub struct Transform {
-    translation: Option<glm::Vec3>,
-    rotation: Option<glm::Quat>,
-    scale: Option<glm::Vec3>,
+    pub translation: Option<glm::Vec3>,
+    pub rotation: Option<glm::Quat>,
+    pub scale: Option<glm::Vec3>,
 }
 
 impl Transform {
+    // The node's authored translation, rotation and scale
+    pub fn from_node(node: &gltf::Node) -> Self {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        Transform {
+            translation: Some(glm::Vec3::from(translation)),
+            rotation: Some(Quaternion::new(
+                rotation[3],
+                rotation[0],
+                rotation[1],
+                rotation[2],
+            )),
+            scale: Some(glm::Vec3::from(scale)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.translation.is_none() && self.rotation.is_none() && self.scale.is_none()
+    }
+
     pub fn matrix(&self) -> glm::Mat4 {
         let mut matrix = glm::Mat4::identity();
         if let Some(translation) = self.translation {
@@ -151,68 +291,625 @@ impl Transform {
         }
         matrix
     }
+
+    // Overwrites the components that are set on the other transform
+    pub fn merge(&mut self, other: &Transform) {
+        if other.translation.is_some() {
+            self.translation = other.translation;
+        }
+        if other.rotation.is_some() {
+            self.rotation = other.rotation;
+        }
+        if other.scale.is_some() {
+            self.scale = other.scale;
+        }
+    }
+
+    // Interpolates towards another transform, starting unset components from the rest pose
+    pub fn blend(&self, other: &Transform, weight: f32, rest: &Transform) -> Transform {
+        let base = self.or_rest(rest);
+        let translation = blend_component(base.translation, other.translation, |from, to| {
+            glm::lerp(&from.unwrap_or_else(glm::Vec3::zeros), &to, weight)
+        });
+        let rotation = blend_component(base.rotation, other.rotation, |from, to| {
+            let from = from.unwrap_or_else(glm::Quat::identity);
+            glm::Quat::from(slerp(&from.coords, &to.coords, weight).normalize())
+        });
+        let scale = blend_component(base.scale, other.scale, |from, to| {
+            glm::lerp(
+                &from.unwrap_or_else(|| glm::vec3(1.0, 1.0, 1.0)),
+                &to,
+                weight,
+            )
+        });
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    // The transform that takes the reference to this transform
+    pub fn relative_to(&self, reference: &Transform) -> Transform {
+        let translation = self.translation.map(|translation| {
+            translation - reference.translation.unwrap_or_else(glm::Vec3::zeros)
+        });
+        let rotation = self.rotation.map(|rotation| {
+            reference
+                .rotation
+                .map_or(rotation, |reference| reference.conjugate() * rotation)
+        });
+        let scale = self.scale.map(|scale| {
+            reference
+                .scale
+                .map_or(scale, |reference| scale.component_div(&reference))
+        });
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    // Layers a relative transform on top of this one, scaled by the weight,
+    // starting unset components from the rest pose
+    pub fn add(&self, delta: &Transform, weight: f32, rest: &Transform) -> Transform {
+        let base = self.or_rest(rest);
+        let translation = blend_component(base.translation, delta.translation, |base, delta| {
+            base.unwrap_or_else(glm::Vec3::zeros) + delta * weight
+        });
+        let rotation = blend_component(base.rotation, delta.rotation, |base, delta| {
+            let identity = glm::Quat::identity();
+            let delta = glm::Quat::from(slerp(&identity.coords, &delta.coords, weight));
+            (base.unwrap_or(identity) * delta).normalize()
+        });
+        let scale = blend_component(base.scale, delta.scale, |base, delta| {
+            let ones = glm::vec3(1.0, 1.0, 1.0);
+            base.unwrap_or(ones)
+                .component_mul(&glm::lerp(&ones, &delta, weight))
+        });
+        Transform {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    fn or_rest(&self, rest: &Transform) -> Transform {
+        Transform {
+            translation: self.translation.or(rest.translation),
+            rotation: self.rotation.or(rest.rotation),
+            scale: self.scale.or(rest.scale),
+        }
+    }
+}
+
+fn blend_component<T, F>(base: Option<T>, other: Option<T>, combine: F) -> Option<T>
+where
+    T: Copy,
+    F: Fn(Option<T>, T) -> T,
+{
+    match other {
+        Some(other) => Some(combine(base, other)),
+        None => base,
+    }
+}
+
+// Animated transforms and morph target weights, keyed by glTF node index
+#[derive(Debug, Default, Clone)]
+pub struct Pose {
+    pub transforms: HashMap<usize, Transform>,
+    pub weights: HashMap<usize, Vec<f32>>,
+}
+
+// Custom properties authored on a glTF object, parsed from its extras
+#[derive(Debug, Default, Clone)]
+pub struct Properties {
+    pub values: HashMap<String, Value>,
+}
+
+impl Properties {
+    // Only object extras have named properties, anything else is ignored
+    pub fn from_extras(extras: &gltf::json::Extras) -> Self {
+        let values = extras
+            .as_ref()
+            .and_then(|extras| serde_json::from_str(extras.get()).ok())
+            .unwrap_or_default();
+        Properties { values }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(Value::as_bool)
+    }
+
+    pub fn get_integer(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(Value::as_i64)
+    }
+
+    pub fn get_float(&self, key: &str) -> Option<f32> {
+        self.get(key)
+            .and_then(Value::as_f64)
+            .map(|value| value as f32)
+    }
+
+    pub fn get_string(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    // Reads a three element array of numbers
+    pub fn get_vec3(&self, key: &str) -> Option<glm::Vec3> {
+        let values = self.get(key)?.as_array()?;
+        if values.len() != 3 {
+            return None;
+        }
+        let mut vector = glm::Vec3::zeros();
+        for (index, value) in values.iter().enumerate() {
+            vector[index] = value.as_f64()? as f32;
+        }
+        Some(vector)
+    }
 }
 
 #[derive(Debug)]
 pub struct Node {
+    pub name: Option<String>,
+    pub properties: Properties,
+    // The authored local transform, exactly as given when it's a matrix
     pub local_transform: glm::Mat4,
+    // The authored local transform, decomposed for animations to replace parts of
+    pub rest_transform: Transform,
+    // Animated components replace the matching rest components
     pub animation_transform: Transform,
     pub mesh: Option<Mesh>,
     pub skin: Option<Skin>,
+    pub weights: Vec<f32>,
+    // The authored morph target weights, restored when no animation sets them
+    pub rest_weights: Vec<f32>,
     pub index: usize,
+    // Cached by update_global_transforms
+    global_transform: glm::Mat4,
+    dirty: bool,
+}
+
+impl Node {
+    pub fn global_transform(&self) -> glm::Mat4 {
+        self.global_transform
+    }
+
+    pub fn local_matrix(&self) -> glm::Mat4 {
+        if self.animation_transform.is_empty() {
+            return self.local_transform;
+        }
+        let mut transform = self.rest_transform;
+        transform.merge(&self.animation_transform);
+        transform.matrix()
+    }
+
+    // Flags the node's subtree for the next update_global_transforms pass
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // Returns the node to its rest pose, the mesh is morphed by the next apply_morph_weights
+    fn reset_animation(&mut self) {
+        if !self.animation_transform.is_empty() {
+            self.animation_transform = Transform::default();
+            self.mark_dirty();
+        }
+        self.weights.clone_from(&self.rest_weights);
+    }
+
+    // Morphs the node's mesh with its current morph target weights
+    pub fn apply_morph_weights(&mut self) {
+        if let Some(mesh) = self.mesh.as_mut() {
+            for primitive in mesh.primitives.iter_mut() {
+                primitive.apply_morph_weights(&self.weights);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Mesh {
+    pub name: Option<String>,
     pub primitives: Vec<Primitive>,
+    pub weights: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub struct MorphTarget {
+    pub positions: Vec<glm::Vec3>,
+    pub normals: Vec<glm::Vec3>,
+    pub tangents: Vec<glm::Vec3>,
+}
+
+// Primitives without an index accessor draw their vertices in order
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DrawMode {
+    Elements,
+    Arrays,
 }
 
 #[derive(Debug)]
 pub struct Primitive {
     pub vao: VertexArrayObject,
+    pub vbo: Buffer,
+    pub ibo: Option<Buffer>,
     pub num_indices: i32,
+    pub num_vertices: i32,
+    pub draw_mode: DrawMode,
+    // The attributes the primitive provides, anything else is left disabled
+    pub attributes: Vec<Semantic>,
+    // The topology the vertices are assembled into
+    pub mode: Mode,
     pub material_index: Option<usize>,
+    // The center of the primitive's bounding box, in the mesh's space
+    pub center: glm::Vec3,
+    pub morph_targets: Vec<MorphTarget>,
+    // Unmorphed vertices, only kept for primitives with morph targets
+    base_vertices: Option<VertexSet>,
+    // The weights the vertices were last morphed with
+    morph_weights: Vec<f32>,
+    // Vertex and index data waiting for upload, dropped once the buffers are created
+    pending_vertices: Option<VertexSet>,
+    pending_indices: Option<Vec<u32>>,
+    uploaded: bool,
+}
+
+impl Primitive {
+    pub fn morphed_vertices(&self, weights: &[f32]) -> Option<VertexSet> {
+        self.base_vertices
+            .as_ref()
+            .map(|base_vertices| base_vertices.morphed(&self.morph_targets, weights))
+    }
+
+    // Primitives that aren't uploaded yet are morphed when they are
+    pub fn apply_morph_weights(&mut self, weights: &[f32]) {
+        if self.base_vertices.is_none() || self.morph_weights == weights {
+            return;
+        }
+        self.morph_weights = weights.to_vec();
+        if !self.uploaded {
+            return;
+        }
+        if let Some(vertex_set) = self.morphed_vertices(weights) {
+            self.vbo.add_data(&vertex_set.pack_vertices());
+            self.vbo.update(&self.vao);
+        }
+    }
+
+    pub fn is_uploaded(&self) -> bool {
+        self.uploaded
+    }
+
+    // Creates the primitive's buffers, on the thread that owns the GL context
+    pub fn upload(&mut self) {
+        if self.uploaded {
+            return;
+        }
+        let (vertex_set, hint) = match self.morphed_vertices(&self.morph_weights) {
+            Some(vertex_set) => (vertex_set, DrawingHint::DynamicDraw),
+            None => (
+                self.pending_vertices
+                    .take()
+                    .expect("Couldn't get primitive vertices!"),
+                DrawingHint::StaticDraw,
+            ),
+        };
+
+        self.vao = VertexArrayObject::new();
+        self.vbo = Buffer::new(BufferKind::Array);
+        self.vbo.add_data(&vertex_set.pack_vertices());
+        self.vbo.upload(&self.vao, hint);
+
+        if let Some(indices) = self.pending_indices.take() {
+            let mut ibo = Buffer::new(BufferKind::Element);
+            ibo.add_data(&indices);
+            ibo.upload(&self.vao, DrawingHint::StaticDraw);
+            self.ibo = Some(ibo);
+        }
+
+        self.vao.configure_layout(&vertex_set.layout());
+        self.uploaded = true;
+    }
+
+    pub fn has_attribute(&self, semantic: Semantic) -> bool {
+        self.attributes.contains(&semantic)
+    }
+
+    // The texture coordinate set to sample for a texture info's tex_coord index,
+    // falling back to the first set when the primitive doesn't provide the one asked for
+    pub fn tex_coord_set(&self, tex_coord: u32) -> u32 {
+        let semantic = match tex_coord {
+            0 => Semantic::TexCoords0,
+            1 => Semantic::TexCoords1,
+            _ => return 0,
+        };
+        if self.has_attribute(semantic) {
+            tex_coord
+        } else {
+            0
+        }
+    }
+
+    // One bit per attribute location, for shaders to check which attributes are present
+    pub fn attribute_mask(&self) -> i32 {
+        self.attributes
+            .iter()
+            .fold(0, |mask, semantic| mask | 1 << semantic.location())
+    }
+
+    pub fn draw(&self) {
+        self.vao.bind();
+        unsafe {
+            match self.draw_mode {
+                DrawMode::Elements => gl::DrawElements(
+                    self.mode.as_gl_enum(),
+                    self.num_indices,
+                    gl::UNSIGNED_INT,
+                    ptr::null(),
+                ),
+                DrawMode::Arrays => gl::DrawArrays(self.mode.as_gl_enum(), 0, self.num_vertices),
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Channel {
     node_index: usize,
+    // Every (scene index, handle) the target node was loaded into, resolved once at load
+    targets: Vec<(usize, NodeHandle)>,
     inputs: Vec<f32>,
     transformations: TransformationSet,
     interpolation: Interpolation,
+    // The key sampled last, checked before searching for keys
     previous_key: usize,
-    previous_time: f32,
+}
+
+// A channel's value at some point in time
+#[derive(Debug, Clone, PartialEq)]
+enum ChannelValue {
+    Translation(glm::Vec3),
+    Rotation(glm::Quat),
+    Scale(glm::Vec3),
+    MorphTargetWeights(Vec<f32>),
+}
+
+impl ChannelValue {
+    fn apply(&self, node: &mut Node) {
+        match self {
+            ChannelValue::Translation(translation) => {
+                node.animation_transform.translation = Some(*translation);
+            }
+            ChannelValue::Rotation(rotation) => {
+                node.animation_transform.rotation = Some(*rotation);
+            }
+            ChannelValue::Scale(scale) => {
+                node.animation_transform.scale = Some(*scale);
+            }
+            // Weight channels leave the node's transform alone
+            ChannelValue::MorphTargetWeights(weights) => {
+                node.weights.clone_from(weights);
+                return;
+            }
+        }
+        node.mark_dirty();
+    }
+}
+
+impl Channel {
+    // Times outside of the keyframes hold the first or last key
+    fn sample(&mut self, time: f32) -> ChannelValue {
+        let first_input = self.inputs.first().unwrap();
+        let last_input = self.inputs.last().unwrap();
+        let time = nalgebra::clamp(time, *first_input, *last_input);
+
+        let (previous_key, next_key) = find_keys(&self.inputs, time, self.previous_key);
+        self.previous_key = previous_key;
+
+        let key_delta = self.inputs[next_key] - self.inputs[previous_key];
+        let normalized_time = if key_delta > 0.0 {
+            (time - self.inputs[previous_key]) / key_delta
+        } else {
+            0.0
+        };
+
+        let interpolation = self.interpolation;
+        match &self.transformations {
+            TransformationSet::Translations(translations) => {
+                ChannelValue::Translation(interpolate(
+                    interpolation,
+                    translations,
+                    previous_key,
+                    next_key,
+                    key_delta,
+                    normalized_time,
+                ))
+            }
+            TransformationSet::Rotations(rotations) => {
+                let rotation = match interpolation {
+                    Interpolation::Linear => slerp(
+                        &rotations[previous_key],
+                        &rotations[next_key],
+                        normalized_time,
+                    ),
+                    _ => interpolate(
+                        interpolation,
+                        rotations,
+                        previous_key,
+                        next_key,
+                        key_delta,
+                        normalized_time,
+                    ),
+                }
+                .normalize();
+                ChannelValue::Rotation(Quaternion::new(
+                    rotation[3],
+                    rotation[0],
+                    rotation[1],
+                    rotation[2],
+                ))
+            }
+            TransformationSet::Scales(scales) => ChannelValue::Scale(interpolate(
+                interpolation,
+                scales,
+                previous_key,
+                next_key,
+                key_delta,
+                normalized_time,
+            )),
+            TransformationSet::MorphTargetWeights(tracks) => {
+                let weights = tracks
+                    .iter()
+                    .map(|track| {
+                        interpolate(
+                            interpolation,
+                            track,
+                            previous_key,
+                            next_key,
+                            key_delta,
+                            normalized_time,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                ChannelValue::MorphTargetWeights(weights)
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Animation {
+    pub name: Option<String>,
+    // The time of the last keyframe across all channels, in seconds
+    pub duration: f32,
     channels: Vec<Channel>,
 }
 
 #[derive(Debug)]
 pub struct Scene {
+    pub name: Option<String>,
     pub node_graphs: Vec<NodeGraph>,
 }
 
+impl Scene {
+    pub fn joint_matrices(&self, handle: NodeHandle) -> Vec<glm::Mat4> {
+        let graph = &self.node_graphs[handle.graph_index];
+        match graph[handle.node_index].skin.as_ref() {
+            Some(skin) => {
+                let mesh_global_transform = graph[handle.node_index].global_transform();
+                skin.joint_matrices(&mesh_global_transform, &self.node_graphs)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub fn update_global_transforms(&mut self) {
+        for graph in self.node_graphs.iter_mut() {
+            update_global_transforms(graph);
+        }
+    }
+
+    // Creates the buffers of every primitive in the scene
+    pub fn upload_primitives(&mut self) {
+        for graph in self.node_graphs.iter_mut() {
+            for node in graph.node_weights_mut() {
+                if let Some(mesh) = node.mesh.as_mut() {
+                    for primitive in mesh.primitives.iter_mut() {
+                        primitive.upload();
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub struct GltfAsset {
-    pub texture_ids: Vec<u32>,
+    // Indexed by image, shared with any other asset that loaded the same image
+    pub textures: Vec<Arc<Texture>>,
     pub gltf: gltf::Document,
     pub scenes: Vec<Scene>,
     pub animations: Vec<Animation>,
+    pub materials: Vec<Material>,
+    // Used by primitives without a material
+    default_material: Material,
+    // Every (scene index, handle) a glTF node index was loaded into
+    node_handles: HashMap<usize, Vec<(usize, NodeHandle)>>,
+    // Every (scene index, handle) that some animation targets
+    animated_nodes: Vec<(usize, NodeHandle)>,
+    // Kept until upload creates their textures
+    images: Vec<gltf::image::Data>,
+    path: String,
 }
 
 impl GltfAsset {
     pub fn from_file(path: &str) -> Self {
-        let (gltf, buffers, textures) = gltf::import(path).expect("Couldn't import file!");
-        let texture_ids = prepare_textures_gl(&textures);
+        GltfAsset::from_file_with_cache(path, &mut TextureCache::new())
+    }
+
+    // Reuses any texture the cache already holds instead of uploading the image again
+    pub fn from_file_with_cache(path: &str, texture_cache: &mut TextureCache) -> Self {
+        let mut asset = GltfAsset::import(path);
+        asset.upload(texture_cache);
+        asset
+    }
+
+    // Loads the asset without touching OpenGL, it can be posed but not drawn until it's uploaded
+    pub fn import(path: &str) -> Self {
+        let (gltf, buffers, images) = gltf::import(path).expect("Couldn't import file!");
         let scenes = prepare_scenes(&gltf, &buffers);
-        let animations = prepare_animations(&gltf, &buffers);
+        let mut animations = prepare_animations(&gltf, &buffers);
+        let node_handles = map_node_handles(&scenes);
+        let mut animated_nodes = Vec::new();
+        for animation in animations.iter_mut() {
+            for channel in animation.channels.iter_mut() {
+                channel.targets = node_handles
+                    .get(&channel.node_index)
+                    .cloned()
+                    .unwrap_or_default();
+                animated_nodes.extend(channel.targets.iter().copied());
+            }
+        }
+        animated_nodes.sort_by_key(|(scene_index, handle)| {
+            (*scene_index, handle.graph_index, handle.node_index)
+        });
+        animated_nodes.dedup();
 
-        GltfAsset {
-            texture_ids,
+        let mut asset = GltfAsset {
+            textures: Vec::new(),
             gltf,
             scenes,
             animations,
+            materials: Vec::new(),
+            default_material: Material::default(),
+            node_handles,
+            animated_nodes,
+            images,
+            path: path.to_string(),
+        };
+        asset.materials = (0..asset.gltf.materials().len())
+            .map(|index| Material::from_gltf(&asset.lookup_material(index)))
+            .collect::<Vec<_>>();
+        asset
+    }
+
+    // Creates the asset's textures and buffers, on the thread that owns the GL context
+    pub fn upload(&mut self, texture_cache: &mut TextureCache) {
+        let images = mem::take(&mut self.images);
+        if !images.is_empty() {
+            self.textures = prepare_textures(&self.gltf, &images, &self.path, texture_cache);
+        }
+        for scene in self.scenes.iter_mut() {
+            scene.upload_primitives();
         }
     }
 
@@ -223,100 +920,312 @@ impl GltfAsset {
             .expect("Couldn't get material!")
     }
 
-    pub fn animate(&mut self, seconds: f32) {
-        // TODO: Allow for specifying a specific animation by name
-        for animation in self.animations.iter_mut() {
-            for channel in animation.channels.iter_mut() {
-                for scene in self.scenes.iter_mut() {
-                    for graph in scene.node_graphs.iter_mut() {
-                        for node_index in graph.node_indices() {
-                            if graph[node_index].index == channel.node_index {
-                                let mut time = seconds % channel.inputs.last().unwrap();
-                                let first_input = channel.inputs.first().unwrap();
-                                if time.lt(first_input) {
-                                    time = *first_input;
-                                }
-
-                                if channel.previous_time > time {
-                                    channel.previous_key = 0;
-                                }
-                                channel.previous_time = time;
-
-                                let mut next_key: usize = 0;
-                                for index in channel.previous_key..channel.inputs.len() {
-                                    let index = index as usize;
-                                    if time <= channel.inputs[index] {
-                                        next_key =
-                                            nalgebra::clamp(index, 1, channel.inputs.len() - 1);
-                                        break;
-                                    }
-                                }
-                                channel.previous_key = nalgebra::clamp(next_key - 1, 0, next_key);
-
-                                let key_delta =
-                                    channel.inputs[next_key] - channel.inputs[channel.previous_key];
-                                let normalized_time =
-                                    (time - channel.inputs[channel.previous_key]) / key_delta;
-
-                                // TODO: Interpolate with other methods
-                                // Only Linear interpolation is used for now
-                                match &channel.transformations {
-                                    TransformationSet::Translations(translations) => {
-                                        let start = translations[channel.previous_key];
-                                        let end = translations[next_key];
-                                        let translation = start.lerp(&end, normalized_time);
-                                        let translation_vec =
-                                            glm::make_vec3(translation.as_slice());
-                                        graph[node_index].animation_transform.translation =
-                                            Some(translation_vec);
-                                    }
-                                    TransformationSet::Rotations(rotations) => {
-                                        let start = rotations[channel.previous_key];
-                                        let end = rotations[next_key];
-                                        let start_quat =
-                                            Quaternion::new(start[3], start[0], start[1], start[2]);
-                                        let end_quat =
-                                            Quaternion::new(end[3], end[0], end[1], end[2]);
-                                        let rotation_quat =
-                                            start_quat.lerp(&end_quat, normalized_time);
-                                        graph[node_index].animation_transform.rotation =
-                                            Some(rotation_quat);
-                                    }
-                                    TransformationSet::Scales(scales) => {
-                                        let start = scales[channel.previous_key];
-                                        let end = scales[next_key];
-                                        let scale = start.lerp(&end, normalized_time);
-                                        let scale_vec = glm::make_vec3(scale.as_slice());
-                                        graph[node_index].animation_transform.scale =
-                                            Some(scale_vec);
-                                    }
-                                    TransformationSet::MorphTargetWeights(_weights) => {
-                                        unimplemented!()
-                                    }
-                                }
-
-                                break;
-                            }
-                        }
+    // A primitive's material, or the default material when it doesn't reference one
+    pub fn material(&self, index: Option<usize>) -> &Material {
+        index.map_or(&self.default_material, |index| &self.materials[index])
+    }
+
+    pub fn find_material(&self, name: &str) -> Option<usize> {
+        self.gltf
+            .materials()
+            .position(|material| material.name() == Some(name))
+    }
+
+    pub fn find_animation(&self, name: &str) -> Option<usize> {
+        self.animations
+            .iter()
+            .position(|animation| animation.name.as_deref() == Some(name))
+    }
+
+    pub fn find_scene(&self, name: &str) -> Option<usize> {
+        self.scenes
+            .iter()
+            .position(|scene| scene.name.as_deref() == Some(name))
+    }
+
+    // Finds the first loaded instance of a named node, as a scene index and handle
+    pub fn find_node(&self, name: &str) -> Option<(usize, NodeHandle)> {
+        let node = self.gltf.nodes().find(|node| node.name() == Some(name))?;
+        self.node_handles
+            .get(&node.index())
+            .and_then(|handles| handles.first())
+            .copied()
+    }
+
+    // Every loaded node with a custom property, as a scene index, handle and node
+    pub fn nodes_with_property<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> impl Iterator<Item = (usize, NodeHandle, &'a Node)> + 'a {
+        self.scenes
+            .iter()
+            .enumerate()
+            .flat_map(|(scene_index, scene)| {
+                scene
+                    .node_graphs
+                    .iter()
+                    .enumerate()
+                    .flat_map(move |(graph_index, graph)| {
+                        graph.node_indices().map(move |node_index| {
+                            let handle = NodeHandle {
+                                graph_index,
+                                node_index,
+                            };
+                            (scene_index, handle, &graph[node_index])
+                        })
+                    })
+            })
+            .filter(move |(_, _, node)| node.properties.contains(key))
+    }
+
+    // The rest pose of the first loaded instance of a glTF node index
+    pub fn rest_transform(&self, node_index: usize) -> Transform {
+        self.node_handles
+            .get(&node_index)
+            .and_then(|handles| handles.first())
+            .map_or_else(Transform::default, |(scene_index, handle)| {
+                self.node(*scene_index, *handle).rest_transform
+            })
+    }
+
+    pub fn node(&self, scene_index: usize, handle: NodeHandle) -> &Node {
+        &self.scenes[scene_index].node_graphs[handle.graph_index][handle.node_index]
+    }
+
+    pub fn node_mut(&mut self, scene_index: usize, handle: NodeHandle) -> &mut Node {
+        &mut self.scenes[scene_index].node_graphs[handle.graph_index][handle.node_index]
+    }
+
+    pub fn primitive(&self, handle: PrimitiveHandle) -> &Primitive {
+        let node = self.node(handle.scene_index, handle.node);
+        &node.mesh.as_ref().expect("Couldn't get mesh!").primitives[handle.primitive_index]
+    }
+
+    // Queues every primitive in the asset by its material's alpha mode, to be drawn with the model matrix,
+    // using the view depth of its bounding box center to order blended primitives
+    pub fn queue_primitives(
+        &self,
+        queue: &mut RenderQueue<PrimitiveInstance>,
+        model: &glm::Mat4,
+        view: &glm::Mat4,
+    ) {
+        for (scene_index, scene) in self.scenes.iter().enumerate() {
+            for (graph_index, graph) in scene.node_graphs.iter().enumerate() {
+                for node_index in graph.node_indices() {
+                    let node = &graph[node_index];
+                    let mesh = match node.mesh.as_ref() {
+                        Some(mesh) => mesh,
+                        None => continue,
+                    };
+                    for (primitive_index, primitive) in mesh.primitives.iter().enumerate() {
+                        let center = model
+                            * node.global_transform()
+                            * glm::vec4(
+                                primitive.center.x,
+                                primitive.center.y,
+                                primitive.center.z,
+                                1.0,
+                            );
+                        let handle = PrimitiveHandle {
+                            scene_index,
+                            node: NodeHandle {
+                                graph_index,
+                                node_index,
+                            },
+                            primitive_index,
+                        };
+                        queue.push(
+                            PrimitiveInstance {
+                                handle,
+                                model: *model,
+                            },
+                            self.material(primitive.material_index).alpha_mode,
+                            view_depth(view, &center.xyz()),
+                        );
                     }
                 }
             }
         }
     }
+
+    // Plays every animation at once, looping each one over its duration
+    pub fn animate(&mut self, seconds: f32) {
+        self.reset_animated_nodes();
+        for index in 0..self.animations.len() {
+            let duration = self.animations[index].duration;
+            let time = if duration > 0.0 {
+                seconds % duration
+            } else {
+                0.0
+            };
+            self.apply_channels(index, time);
+        }
+        self.finish_animation();
+    }
+
+    // Poses the scenes with a single animation at the given time in seconds,
+    // nodes it doesn't animate are left in their rest pose
+    pub fn sample_animation(&mut self, animation_index: usize, time: f32) {
+        self.reset_animated_nodes();
+        self.apply_channels(animation_index, time);
+        self.finish_animation();
+    }
+
+    fn apply_channels(&mut self, animation_index: usize, time: f32) {
+        let animation = &mut self.animations[animation_index];
+        for channel in animation.channels.iter_mut() {
+            let value = channel.sample(time);
+            for (scene_index, handle) in channel.targets.iter() {
+                let graph = &mut self.scenes[*scene_index].node_graphs[handle.graph_index];
+                value.apply(&mut graph[handle.node_index]);
+            }
+        }
+    }
+
+    // Clears what earlier poses left on the animated nodes,
+    // so nodes the new pose doesn't target go back to rest
+    fn reset_animated_nodes(&mut self) {
+        for (scene_index, handle) in self.animated_nodes.iter() {
+            let graph = &mut self.scenes[*scene_index].node_graphs[handle.graph_index];
+            graph[handle.node_index].reset_animation();
+        }
+    }
+
+    // Morphs the meshes whose weights changed and updates every scene's transforms once
+    fn finish_animation(&mut self) {
+        for (scene_index, handle) in self.animated_nodes.iter() {
+            let graph = &mut self.scenes[*scene_index].node_graphs[handle.graph_index];
+            graph[handle.node_index].apply_morph_weights();
+        }
+        for scene in self.scenes.iter_mut() {
+            scene.update_global_transforms();
+        }
+    }
+
+    // Samples a single animation without modifying the scenes
+    pub fn sample_pose(&mut self, animation_index: usize, time: f32) -> Pose {
+        let mut pose = Pose::default();
+        let animation = &mut self.animations[animation_index];
+        for channel in animation.channels.iter_mut() {
+            let node_index = channel.node_index;
+            match channel.sample(time) {
+                ChannelValue::Translation(translation) => {
+                    pose.transforms.entry(node_index).or_default().translation = Some(translation);
+                }
+                ChannelValue::Rotation(rotation) => {
+                    pose.transforms.entry(node_index).or_default().rotation = Some(rotation);
+                }
+                ChannelValue::Scale(scale) => {
+                    pose.transforms.entry(node_index).or_default().scale = Some(scale);
+                }
+                // Weight channels leave the node's transform alone
+                ChannelValue::MorphTargetWeights(weights) => {
+                    pose.weights.insert(node_index, weights);
+                }
+            }
+        }
+        pose
+    }
+
+    // Writes a pose into the animation transforms of the nodes it targets,
+    // animated nodes the pose doesn't target are returned to rest
+    pub fn apply_pose(&mut self, pose: &Pose) {
+        self.reset_animated_nodes();
+        for (node, transform) in pose.transforms.iter() {
+            for (scene_index, handle) in self.node_handles.get(node).into_iter().flatten() {
+                let graph = &mut self.scenes[*scene_index].node_graphs[handle.graph_index];
+                let node = &mut graph[handle.node_index];
+                node.animation_transform.merge(transform);
+                node.mark_dirty();
+            }
+        }
+        for (node, weights) in pose.weights.iter() {
+            for (scene_index, handle) in self.node_handles.get(node).into_iter().flatten() {
+                let graph = &mut self.scenes[*scene_index].node_graphs[handle.graph_index];
+                graph[handle.node_index].weights.clone_from(weights);
+            }
+        }
+        self.finish_animation();
+    }
+}
+
+// Finds the keys surrounding a time, checking the previously used keys before searching
+fn find_keys(inputs: &[f32], time: f32, previous_key: usize) -> (usize, usize) {
+    let last_key = inputs.len() - 1;
+    if last_key == 0 {
+        return (0, 0);
+    }
+
+    if previous_key < last_key && inputs[previous_key] <= time && time <= inputs[previous_key + 1] {
+        return (previous_key, previous_key + 1);
+    }
+
+    let next_key =
+        match inputs.binary_search_by(|input| input.partial_cmp(&time).unwrap_or(Ordering::Less)) {
+            Ok(index) | Err(index) => nalgebra::clamp(index, 1, last_key),
+        };
+    (next_key - 1, next_key)
+}
+
+// Samples a keyframe set between two keys
+// Spline interpolations store extra tangent values alongside the keyframe values
+fn interpolate<T>(
+    interpolation: Interpolation,
+    values: &[T],
+    previous_key: usize,
+    next_key: usize,
+    key_delta: f32,
+    normalized_time: f32,
+) -> T
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    let time = normalized_time;
+    let time_squared = time * time;
+    let time_cubed = time_squared * time;
+    match interpolation {
+        Interpolation::Linear => values[previous_key] * (1.0 - time) + values[next_key] * time,
+        Interpolation::Step => {
+            if time < 1.0 {
+                values[previous_key]
+            } else {
+                values[next_key]
+            }
+        }
+        Interpolation::CubicSpline => {
+            // Values are stored as (in-tangent, value, out-tangent) triplets
+            let start = values[previous_key * 3 + 1];
+            let start_out_tangent = values[previous_key * 3 + 2];
+            let end_in_tangent = values[next_key * 3];
+            let end = values[next_key * 3 + 1];
+            start * (2.0 * time_cubed - 3.0 * time_squared + 1.0)
+                + start_out_tangent * ((time_cubed - 2.0 * time_squared + time) * key_delta)
+                + end * (-2.0 * time_cubed + 3.0 * time_squared)
+                + end_in_tangent * ((time_cubed - time_squared) * key_delta)
+        }
+    }
 }
 
-// TODO: Write this method for vec3's and vec4's
-// fn interpolate(interpolation: Interpolation) {
-//     match interpolation {
-//         Interpolation::Linear => {}
-//         Interpolation::Step => {}
-//         Interpolation::CatmullRomSpline => {}
-//         Interpolation::CubicSpline => {}
-//     }
-// }
+// Spherical linear interpolation along the shortest path between two (x, y, z, w) quaternions
+fn slerp(start: &glm::Vec4, end: &glm::Vec4, time: f32) -> glm::Vec4 {
+    let mut end = *end;
+    let mut cos_theta = start.dot(&end);
+    if cos_theta < 0.0 {
+        end = -end;
+        cos_theta = -cos_theta;
+    }
+
+    // Nearly parallel quaternions are lerped to avoid dividing by a vanishing sine
+    if cos_theta > 0.9995 {
+        return start * (1.0 - time) + end * time;
+    }
+
+    let theta = cos_theta.acos();
+    let sin_theta = theta.sin();
+    start * (((1.0 - time) * theta).sin() / sin_theta) + end * ((time * theta).sin() / sin_theta)
+}
 
 fn prepare_animations(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<Animation> {
-    // TODO: load names if present as well
     let mut animations = Vec::new();
     for animation in gltf.animations() {
         let mut channels = Vec::new();
@@ -325,46 +1234,73 @@ fn prepare_animations(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) ->
             let interpolation = sampler.interpolation();
             let node_index = channel.target().node().index();
             let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
-            let inputs = reader.read_inputs().unwrap().collect::<Vec<_>>();
+            let inputs = read_accessor(reader.read_inputs().unwrap(), sampler.input().count());
             let outputs = reader.read_outputs().unwrap();
+            let output_count = sampler.output().count();
             let transformations: TransformationSet;
             match outputs {
                 ReadOutputs::Translations(translations) => {
-                    let translations = translations.map(glm::Vec3::from).collect::<Vec<_>>();
+                    let translations =
+                        read_accessor(translations.map(glm::Vec3::from), output_count);
                     transformations = TransformationSet::Translations(translations);
                 }
                 ReadOutputs::Rotations(rotations) => {
-                    let rotations = rotations
-                        .into_f32()
-                        .map(glm::Vec4::from)
-                        .collect::<Vec<_>>();
+                    let rotations = read_rotations(rotations, output_count);
                     transformations = TransformationSet::Rotations(rotations);
                 }
                 ReadOutputs::Scales(scales) => {
-                    let scales = scales.map(glm::Vec3::from).collect::<Vec<_>>();
+                    let scales = read_accessor(scales.map(glm::Vec3::from), output_count);
                     transformations = TransformationSet::Scales(scales);
                 }
                 ReadOutputs::MorphTargetWeights(weights) => {
-                    let morph_target_weights = weights.into_f32().collect::<Vec<_>>();
-                    transformations = TransformationSet::MorphTargetWeights(morph_target_weights);
+                    // Weights are interleaved, one value per morph target for each output
+                    let weights = read_morph_target_weights(weights, output_count);
+                    let number_of_targets = channel
+                        .target()
+                        .node()
+                        .mesh()
+                        .and_then(|mesh| {
+                            mesh.primitives()
+                                .next()
+                                .map(|primitive| primitive.morph_targets().len())
+                        })
+                        .map_or(1, |number_of_targets| number_of_targets.max(1));
+                    let tracks = (0..number_of_targets)
+                        .map(|target| {
+                            weights
+                                .iter()
+                                .skip(target)
+                                .step_by(number_of_targets)
+                                .copied()
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>();
+                    transformations = TransformationSet::MorphTargetWeights(tracks);
                 }
             }
             channels.push(Channel {
                 node_index,
+                targets: Vec::new(),
                 inputs,
                 transformations,
                 interpolation,
                 previous_key: 0,
-                previous_time: 0.0,
             });
         }
-        animations.push(Animation { channels });
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.inputs.last())
+            .fold(0.0, |duration: f32, input| duration.max(*input));
+        animations.push(Animation {
+            name: animation.name().map(str::to_string),
+            duration,
+            channels,
+        });
     }
     animations
 }
 
 // TODO: Make graph a collection of collections of graphs belonging to the scene (Vec<Vec<NodeGraph>>)
-// TODO: Load names for scenes and nodes
 fn prepare_scenes(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<Scene> {
     let mut scenes: Vec<Scene> = Vec::new();
     for scene in gltf.scenes() {
@@ -374,47 +1310,193 @@ fn prepare_scenes(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<
             visit_children(&node, &buffers, &mut node_graph, NodeIndex::new(0_usize));
             node_graphs.push(node_graph);
         }
-        scenes.push(Scene { node_graphs });
+        resolve_joints(&mut node_graphs);
+        let mut scene = Scene {
+            name: scene.name().map(str::to_string),
+            node_graphs,
+        };
+        scene.update_global_transforms();
+        scenes.push(scene);
     }
     scenes
 }
 
-fn prepare_textures_gl(textures: &[gltf::image::Data]) -> Vec<u32> {
-    let mut texture_ids = Vec::new();
-    for texture in textures.iter() {
-        // gltf 2.0 only supports 2D texture targets
-        let target = gl::TEXTURE_2D;
-        let mut texture_id = 0;
-        unsafe {
-            gl::GenTextures(1, &mut texture_id);
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(target, texture_id);
-        }
-        let pixel_format = match texture.format {
-            Format::R8 => gl::RED,
-            Format::R8G8 => gl::RG,
-            Format::R8G8B8 => gl::RGB,
-            Format::R8G8B8A8 => gl::RGBA,
-            Format::B8G8R8 => gl::BGR,
-            Format::B8G8R8A8 => gl::BGRA,
-        };
-        unsafe {
-            gl::TexImage2D(
-                target,
-                0,
-                pixel_format as i32,
-                texture.width as i32,
-                texture.height as i32,
-                0,
-                pixel_format,
-                gl::UNSIGNED_BYTE,
-                texture.pixels.as_ptr() as *const GLvoid,
+// Maps each glTF node index to every place it was loaded, so animation targets aren't searched for
+fn map_node_handles(scenes: &[Scene]) -> HashMap<usize, Vec<(usize, NodeHandle)>> {
+    let mut node_handles = HashMap::new();
+    for (scene_index, scene) in scenes.iter().enumerate() {
+        for (graph_index, graph) in scene.node_graphs.iter().enumerate() {
+            for node_index in graph.node_indices() {
+                node_handles
+                    .entry(graph[node_index].index)
+                    .or_insert_with(Vec::new)
+                    .push((
+                        scene_index,
+                        NodeHandle {
+                            graph_index,
+                            node_index,
+                        },
+                    ));
+            }
+        }
+    }
+    node_handles
+}
+
+// Joints may live in any of the scene's graphs
+fn resolve_joints(node_graphs: &mut [NodeGraph]) {
+    let mut handles = HashMap::new();
+    for (graph_index, graph) in node_graphs.iter().enumerate() {
+        for node_index in graph.node_indices() {
+            handles.insert(
+                graph[node_index].index,
+                NodeHandle {
+                    graph_index,
+                    node_index,
+                },
             );
-            gl::GenerateMipmap(target);
         }
-        texture_ids.push(texture_id);
     }
-    texture_ids
+
+    for graph in node_graphs.iter_mut() {
+        for node in graph.node_weights_mut() {
+            if let Some(skin) = node.skin.as_mut() {
+                for joint in skin.joints.iter_mut() {
+                    joint.handle = handles.get(&joint.index).copied();
+                }
+            }
+        }
+    }
+}
+
+// Images are shared through the cache, keyed by their resolved file path,
+// or by a hash of their pixels when they're embedded in the asset
+fn prepare_textures(
+    gltf: &gltf::Document,
+    images: &[gltf::image::Data],
+    path: &str,
+    texture_cache: &mut TextureCache,
+) -> Vec<Arc<Texture>> {
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let color_spaces = image_color_spaces(gltf);
+    let samplers = image_samplers(gltf);
+    gltf.images()
+        .zip(images.iter())
+        .map(|(image, data)| {
+            let index = image.index();
+            let source = match image.source() {
+                gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                    let path = directory.join(uri);
+                    fs::canonicalize(&path)
+                        .unwrap_or(path)
+                        .to_string_lossy()
+                        .into_owned()
+                }
+                _ => format!("#{:016x}", image_hash(data)),
+            };
+            // The same image is stored separately for each color space and sampler it's used with
+            let key = format!("{}:{:?}:{:?}", source, color_spaces[index], samplers[index]);
+            texture_cache.retrieve_or_create(&key, || {
+                create_texture(data, color_spaces[index], samplers[index])
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+fn image_hash(data: &gltf::image::Data) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.format.hash(&mut hasher);
+    data.width.hash(&mut hasher);
+    data.height.hash(&mut hasher);
+    data.pixels.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn create_texture(
+    data: &gltf::image::Data,
+    color_space: ColorSpace,
+    sampler: SamplerDescriptor,
+) -> Texture {
+    let (pixel_format, data_type) = match data.format {
+        Format::R8 => (gl::RED, gl::UNSIGNED_BYTE),
+        Format::R8G8 => (gl::RG, gl::UNSIGNED_BYTE),
+        Format::R8G8B8 => (gl::RGB, gl::UNSIGNED_BYTE),
+        Format::R8G8B8A8 => (gl::RGBA, gl::UNSIGNED_BYTE),
+        Format::B8G8R8 => (gl::BGR, gl::UNSIGNED_BYTE),
+        Format::B8G8R8A8 => (gl::BGRA, gl::UNSIGNED_BYTE),
+        Format::R16 => (gl::RED, gl::UNSIGNED_SHORT),
+        Format::R16G16 => (gl::RG, gl::UNSIGNED_SHORT),
+        Format::R16G16B16 => (gl::RGB, gl::UNSIGNED_SHORT),
+        Format::R16G16B16A16 => (gl::RGBA, gl::UNSIGNED_SHORT),
+    };
+    // gltf 2.0 only supports 2D texture targets
+    let mut texture = Texture::new(gl::TEXTURE_2D);
+    texture.set_color_space(color_space);
+    texture.set_sampler(sampler);
+    texture.load_data_of_type(
+        data.width,
+        data.height,
+        &data.pixels,
+        pixel_format,
+        data_type,
+        gl::TEXTURE_2D,
+    );
+    texture
+}
+
+// Sampler state is stored with the image's texture, so an image shared by
+// glTF textures with different samplers uses the last one's
+fn image_samplers(gltf: &gltf::Document) -> Vec<SamplerDescriptor> {
+    let mut samplers = vec![SamplerDescriptor::default(); gltf.images().len()];
+    for texture in gltf.textures() {
+        samplers[texture.source().index()] = sampler_descriptor(&texture.sampler());
+    }
+    samplers
+}
+
+// Base color and emissive images hold sRGB color data, everything else is linear
+// An image used both ways is treated as color data
+fn image_color_spaces(gltf: &gltf::Document) -> Vec<ColorSpace> {
+    let mut color_spaces = vec![ColorSpace::Linear; gltf.images().len()];
+    for material in gltf.materials() {
+        let color_textures = material
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .into_iter()
+            .chain(material.emissive_texture());
+        for info in color_textures {
+            color_spaces[info.texture().source().index()] = ColorSpace::Srgb;
+        }
+    }
+    color_spaces
+}
+
+// Filters a sampler leaves undefined default to trilinear filtering
+fn sampler_descriptor(sampler: &gltf::texture::Sampler) -> SamplerDescriptor {
+    let wrapping_mode = |mode| match mode {
+        gltf::texture::WrappingMode::ClampToEdge => WrappingMode::ClampToEdge,
+        gltf::texture::WrappingMode::MirroredRepeat => WrappingMode::MirroredRepeat,
+        gltf::texture::WrappingMode::Repeat => WrappingMode::Repeat,
+    };
+    SamplerDescriptor {
+        wrap_s: wrapping_mode(sampler.wrap_s()),
+        wrap_t: wrapping_mode(sampler.wrap_t()),
+        mag_filter: match sampler.mag_filter() {
+            Some(gltf::texture::MagFilter::Nearest) => MagFilter::Nearest,
+            Some(gltf::texture::MagFilter::Linear) | None => MagFilter::Linear,
+        },
+        min_filter: match sampler.min_filter() {
+            Some(gltf::texture::MinFilter::Nearest) => MinFilter::Nearest,
+            Some(gltf::texture::MinFilter::Linear) => MinFilter::Linear,
+            Some(gltf::texture::MinFilter::NearestMipmapNearest) => MinFilter::NearestMipmapNearest,
+            Some(gltf::texture::MinFilter::LinearMipmapNearest) => MinFilter::LinearMipmapNearest,
+            Some(gltf::texture::MinFilter::NearestMipmapLinear) => MinFilter::NearestMipmapLinear,
+            Some(gltf::texture::MinFilter::LinearMipmapLinear) | None => {
+                MinFilter::LinearMipmapLinear
+            }
+        },
+        ..SamplerDescriptor::default()
+    }
 }
 
 fn visit_children(
@@ -423,13 +1505,29 @@ fn visit_children(
     node_graph: &mut NodeGraph,
     parent_index: NodeIndex,
 ) {
-    let node_info = Node {
+    let mesh = load_mesh(node, buffers);
+    let weights = node.weights().map_or_else(
+        || {
+            mesh.as_ref()
+                .map_or(Vec::new(), |mesh| mesh.weights.clone())
+        },
+        |weights| weights.to_vec(),
+    );
+    let mut node_info = Node {
+        name: node.name().map(str::to_string),
+        properties: Properties::from_extras(node.extras()),
         local_transform: determine_transform(node),
+        rest_transform: Transform::from_node(node),
         animation_transform: Transform::default(),
-        mesh: load_mesh(node, buffers),
+        mesh,
         skin: load_skin(node, buffers),
+        rest_weights: weights.clone(),
+        weights,
         index: node.index(),
+        global_transform: glm::Mat4::identity(),
+        dirty: true,
     };
+    node_info.apply_morph_weights();
 
     let node_index = node_graph.add_node(node_info);
     if parent_index != node_index {
@@ -444,15 +1542,37 @@ fn visit_children(
 fn load_mesh(node: &gltf::Node, buffers: &[gltf::buffer::Data]) -> Option<Mesh> {
     if let Some(mesh) = node.mesh() {
         let mut all_primitive_info = Vec::new();
+        let mut number_of_targets = 0;
         for primitive in mesh.primitives() {
-            let (vertex_set, indices) = read_buffer_data(&primitive, &buffers);
-            let mut primitive_info = prepare_primitive_gl(&vertex_set, &indices);
+            let (mut vertex_set, mut indices) = read_buffer_data(&primitive, &buffers);
+            let mut morph_targets = read_morph_targets(&primitive, &buffers);
+            generate_missing_attributes(
+                &primitive,
+                &mut vertex_set,
+                &mut indices,
+                &mut morph_targets,
+            );
+            let mut primitive_info = prepare_primitive(vertex_set, indices);
             let material_index = primitive.material().index();
             primitive_info.material_index = material_index;
+            primitive_info.mode = primitive.mode();
+            let bounds = primitive.bounding_box();
+            primitive_info.center =
+                (glm::Vec3::from(bounds.min) + glm::Vec3::from(bounds.max)) * 0.5;
+            if !morph_targets.is_empty() {
+                number_of_targets = morph_targets.len();
+                primitive_info.morph_targets = morph_targets;
+                primitive_info.base_vertices = primitive_info.pending_vertices.take();
+            }
             all_primitive_info.push(primitive_info);
         }
+        let weights = mesh
+            .weights()
+            .map_or(vec![0.0; number_of_targets], |weights| weights.to_vec());
         Some(Mesh {
+            name: mesh.name().map(str::to_string),
             primitives: all_primitive_info,
+            weights,
         })
     } else {
         None
@@ -478,6 +1598,7 @@ fn load_skin(node: &gltf::Node, buffers: &[gltf::buffer::Data]) -> Option<Skin>
             joints.push(Joint {
                 inverse_bind_matrix,
                 index: joint_node.index(),
+                handle: None,
             });
         }
 
@@ -501,39 +1622,36 @@ fn determine_transform(node: &gltf::Node) -> glm::Mat4 {
 fn read_buffer_data(
     primitive: &gltf::Primitive,
     buffers: &[gltf::buffer::Data],
-) -> (VertexSet, Vec<u32>) {
+) -> (VertexSet, Option<Vec<u32>>) {
     let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let count = vertex_count(primitive);
 
     let positions = reader.read_positions().map_or(Vec::new(), |positions| {
-        positions.map(glm::Vec3::from).collect::<Vec<_>>()
+        read_accessor(positions.map(glm::Vec3::from), count)
     });
 
     let normals = reader.read_normals().map_or(Vec::new(), |normals| {
-        normals.map(glm::Vec3::from).collect::<Vec<_>>()
+        read_accessor(normals.map(glm::Vec3::from), count)
     });
 
-    let convert_coords = |coords: gltf::mesh::util::ReadTexCoords<'_>| -> Vec<glm::Vec2> {
-        coords.into_f32().map(glm::Vec2::from).collect::<Vec<_>>()
-    };
+    let convert_coords = |coords| read_tex_coords(coords, count);
     let tex_coords_0 = reader.read_tex_coords(0).map_or(Vec::new(), convert_coords);
     let tex_coords_1 = reader.read_tex_coords(1).map_or(Vec::new(), convert_coords);
 
     let joints_0 = reader.read_joints(0).map_or(Vec::new(), |joints| {
-        joints
-            .into_u16()
-            .map(|joint| {
-                glm::vec4(
-                    joint[0] as f32,
-                    joint[1] as f32,
-                    joint[2] as f32,
-                    joint[3] as f32,
-                )
-            })
-            .collect::<Vec<_>>()
+        read_accessor(joints.into_u16().map(glm::TVec4::<u16>::from), count)
     });
 
-    let weights_0 = reader.read_weights(0).map_or(Vec::new(), |weights| {
-        weights.into_f32().map(glm::Vec4::from).collect::<Vec<_>>()
+    let weights_0 = reader
+        .read_weights(0)
+        .map_or(Vec::new(), |weights| read_weights(weights, count));
+
+    let colors_0 = reader
+        .read_colors(0)
+        .map_or(Vec::new(), |colors| read_colors(colors, count));
+
+    let tangents = reader.read_tangents().map_or(Vec::new(), |tangents| {
+        read_accessor(tangents.map(glm::Vec4::from), count)
     });
 
     let mut vertices = Vec::new();
@@ -545,40 +1663,294 @@ fn read_buffer_data(
             tex_coords_1: tex_coords_1.get(index).copied(),
             joints_0: joints_0.get(index).copied(),
             weights_0: weights_0.get(index).copied(),
+            tangent: tangents.get(index).copied(),
+            color_0: colors_0.get(index).copied(),
         });
     }
 
-    let indices = reader
-        .read_indices()
-        .map(|read_indices| read_indices.into_u32().collect::<Vec<_>>())
-        .unwrap();
+    let indices = reader.read_indices().map(|read_indices| {
+        let count = primitive.indices().map_or(0, |accessor| accessor.count());
+        read_accessor(read_indices.into_u32(), count)
+    });
 
     (VertexSet { vertices }, indices)
 }
 
-fn prepare_primitive_gl(vertex_set: &VertexSet, indices: &[u32]) -> Primitive {
-    let vao = VertexArrayObject::new();
-    let mut vbo = Buffer::new(BufferKind::Array);
-    let mut ibo = Buffer::new(BufferKind::Element);
+// Every attribute accessor of a primitive has the same count as its positions
+fn vertex_count(primitive: &gltf::Primitive) -> usize {
+    primitive
+        .get(&gltf::Semantic::Positions)
+        .map_or(0, |accessor| accessor.count())
+}
+
+// Reads at most an accessor's count of items, one at a time
+// The gltf crate's sparse iterators report a wrong size hint, and never end
+// when the accessor has no base buffer view and starts out as zeros
+fn read_accessor<T>(items: impl Iterator<Item = T>, count: usize) -> Vec<T> {
+    let mut values = Vec::with_capacity(count);
+    for item in items.take(count) {
+        values.push(item);
+    }
+    values
+}
+
+// glTF requires flat normals when they are missing, and tangents are needed for normal mapping
+fn generate_missing_attributes(
+    primitive: &gltf::Primitive,
+    vertex_set: &mut VertexSet,
+    indices: &mut Option<Vec<u32>>,
+    morph_targets: &mut [MorphTarget],
+) {
+    if primitive.mode() != Mode::Triangles || vertex_set.vertices.is_empty() {
+        return;
+    }
+
+    if vertex_set.vertices[0].normal.is_none() {
+        // Flat shading needs a vertex per triangle corner, so the primitive becomes non-indexed
+        let vertex_indices = indices
+            .take()
+            .unwrap_or_else(|| (0..vertex_set.vertices.len() as u32).collect::<Vec<_>>());
+        let positions = vertex_set
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position.unwrap_or_else(glm::Vec3::zeros))
+            .collect::<Vec<_>>();
+        let normals = flat_normals(&positions, &vertex_indices);
+
+        vertex_set.vertices = unweld(&vertex_set.vertices, &vertex_indices);
+        vertex_set.vertices.truncate(normals.len());
+        for (vertex, normal) in vertex_set.vertices.iter_mut().zip(normals.iter()) {
+            vertex.normal = Some(*normal);
+        }
+
+        let unweld_displacements = |displacements: &mut Vec<glm::Vec3>| {
+            if !displacements.is_empty() {
+                *displacements = unweld(displacements, &vertex_indices);
+            }
+        };
+        for morph_target in morph_targets.iter_mut() {
+            unweld_displacements(&mut morph_target.positions);
+            unweld_displacements(&mut morph_target.normals);
+            unweld_displacements(&mut morph_target.tangents);
+        }
+    }
+
+    let normal_texture = match primitive.material().normal_texture() {
+        Some(normal_texture) => normal_texture,
+        None => return,
+    };
+    let first_vertex = vertex_set.vertices[0];
+    let has_tex_coords = match normal_texture.tex_coord() {
+        0 => first_vertex.tex_coords_0.is_some(),
+        _ => first_vertex.tex_coords_1.is_some(),
+    };
+    if first_vertex.tangent.is_some() || !has_tex_coords {
+        return;
+    }
+
+    let positions = vertex_set
+        .vertices
+        .iter()
+        .map(|vertex| vertex.position.unwrap_or_else(glm::Vec3::zeros))
+        .collect::<Vec<_>>();
+    let normals = vertex_set
+        .vertices
+        .iter()
+        .map(|vertex| vertex.normal.unwrap_or_else(glm::Vec3::zeros))
+        .collect::<Vec<_>>();
+    let tex_coords = vertex_set
+        .vertices
+        .iter()
+        .map(|vertex| match normal_texture.tex_coord() {
+            0 => vertex.tex_coords_0,
+            _ => vertex.tex_coords_1,
+        })
+        .map(|tex_coords| tex_coords.unwrap_or_else(glm::Vec2::zeros))
+        .collect::<Vec<_>>();
+    // Non-indexed primitives have no shared vertices, so only indexed ones are ever split
+    let mut vertex_indices = indices
+        .clone()
+        .unwrap_or_else(|| (0..vertex_set.vertices.len() as u32).collect::<Vec<_>>());
+    let Tangents {
+        tangents,
+        split_from,
+    } = tangents(&positions, &normals, &tex_coords, &mut vertex_indices);
+    split(&mut vertex_set.vertices, &split_from);
+    for morph_target in morph_targets.iter_mut() {
+        split(&mut morph_target.positions, &split_from);
+        split(&mut morph_target.normals, &split_from);
+        split(&mut morph_target.tangents, &split_from);
+    }
+    if let Some(indices) = indices.as_mut() {
+        *indices = vertex_indices;
+    }
+    for (vertex, tangent) in vertex_set.vertices.iter_mut().zip(tangents.iter()) {
+        vertex.tangent = Some(*tangent);
+    }
+}
+
+// The gltf crate's own float conversion divides u8 values by the wrong maximum,
+// so normalized integers are converted by hand
+fn read_tex_coords(tex_coords: ReadTexCoords, count: usize) -> Vec<glm::Vec2> {
+    match tex_coords {
+        ReadTexCoords::U8(tex_coords) => read_accessor(
+            tex_coords.map(|tex_coords| glm::Vec2::from(tex_coords.map(normalize_u8))),
+            count,
+        ),
+        ReadTexCoords::U16(tex_coords) => read_accessor(
+            tex_coords.map(|tex_coords| glm::Vec2::from(tex_coords.map(normalize_u16))),
+            count,
+        ),
+        ReadTexCoords::F32(tex_coords) => read_accessor(tex_coords.map(glm::Vec2::from), count),
+    }
+}
+
+fn read_weights(weights: ReadWeights, count: usize) -> Vec<glm::Vec4> {
+    match weights {
+        ReadWeights::U8(weights) => read_accessor(
+            weights.map(|weights| glm::Vec4::from(weights.map(normalize_u8))),
+            count,
+        ),
+        ReadWeights::U16(weights) => read_accessor(
+            weights.map(|weights| glm::Vec4::from(weights.map(normalize_u16))),
+            count,
+        ),
+        ReadWeights::F32(weights) => read_accessor(weights.map(glm::Vec4::from), count),
+    }
+}
+
+// The count is the number of weights, one per morph target for each output
+fn read_morph_target_weights(weights: MorphTargetWeights, count: usize) -> Vec<f32> {
+    match weights {
+        MorphTargetWeights::I8(weights) => read_accessor(weights.map(normalize_i8), count),
+        MorphTargetWeights::U8(weights) => read_accessor(weights.map(normalize_u8), count),
+        MorphTargetWeights::I16(weights) => read_accessor(weights.map(normalize_i16), count),
+        MorphTargetWeights::U16(weights) => read_accessor(weights.map(normalize_u16), count),
+        MorphTargetWeights::F32(weights) => read_accessor(weights, count),
+    }
+}
 
-    vbo.add_data(&vertex_set.pack_vertices());
-    vbo.upload(&vao, DrawingHint::StaticDraw);
+fn read_rotations(rotations: Rotations, count: usize) -> Vec<glm::Vec4> {
+    match rotations {
+        Rotations::I8(rotations) => read_accessor(
+            rotations.map(|rotation| glm::Vec4::from(rotation.map(normalize_i8))),
+            count,
+        ),
+        Rotations::U8(rotations) => read_accessor(
+            rotations.map(|rotation| glm::Vec4::from(rotation.map(normalize_u8))),
+            count,
+        ),
+        Rotations::I16(rotations) => read_accessor(
+            rotations.map(|rotation| glm::Vec4::from(rotation.map(normalize_i16))),
+            count,
+        ),
+        Rotations::U16(rotations) => read_accessor(
+            rotations.map(|rotation| glm::Vec4::from(rotation.map(normalize_u16))),
+            count,
+        ),
+        Rotations::F32(rotations) => read_accessor(rotations.map(glm::Vec4::from), count),
+    }
+}
 
-    ibo.add_data(indices);
-    ibo.upload(&vao, DrawingHint::StaticDraw);
+// The most negative value is clamped, so that -128 and -127 both map to -1
+fn normalize_i8(value: i8) -> f32 {
+    (f32::from(value) / f32::from(i8::MAX)).max(-1.0)
+}
+
+fn normalize_i16(value: i16) -> f32 {
+    (f32::from(value) / f32::from(i16::MAX)).max(-1.0)
+}
+
+fn normalize_u8(value: u8) -> f32 {
+    f32::from(value) / f32::from(u8::MAX)
+}
+
+fn normalize_u16(value: u16) -> f32 {
+    f32::from(value) / f32::from(u16::MAX)
+}
 
-    let data_lengths = vertex_set.data_lengths();
-    let total_length = data_lengths.iter().sum();
-    let mut offset = 0;
-    for (current_attribute, length) in data_lengths.iter().enumerate() {
-        vao.configure_attribute(current_attribute as u32, *length, total_length, offset);
-        offset += length;
+// RGB colors are given an opaque alpha
+fn read_colors(colors: ReadColors, count: usize) -> Vec<glm::Vec4> {
+    let u8_max = f32::from(u8::MAX);
+    let u16_max = f32::from(u16::MAX);
+    match colors {
+        ReadColors::RgbU8(colors) => read_accessor(
+            colors
+                .map(|color| glm::vec4(color[0], color[1], color[2], u8::MAX))
+                .map(|color| color.map(f32::from) / u8_max),
+            count,
+        ),
+        ReadColors::RgbU16(colors) => read_accessor(
+            colors
+                .map(|color| glm::vec4(color[0], color[1], color[2], u16::MAX))
+                .map(|color| color.map(f32::from) / u16_max),
+            count,
+        ),
+        ReadColors::RgbF32(colors) => read_accessor(
+            colors.map(|color| glm::vec4(color[0], color[1], color[2], 1.0)),
+            count,
+        ),
+        ReadColors::RgbaU8(colors) => read_accessor(
+            colors
+                .map(|color| glm::vec4(color[0], color[1], color[2], color[3]))
+                .map(|color| color.map(f32::from) / u8_max),
+            count,
+        ),
+        ReadColors::RgbaU16(colors) => read_accessor(
+            colors
+                .map(|color| glm::vec4(color[0], color[1], color[2], color[3]))
+                .map(|color| color.map(f32::from) / u16_max),
+            count,
+        ),
+        ReadColors::RgbaF32(colors) => read_accessor(colors.map(glm::Vec4::from), count),
     }
+}
+
+fn read_morph_targets(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<MorphTarget> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let count = vertex_count(primitive);
+    reader
+        .read_morph_targets()
+        .map(|(positions, normals, tangents)| MorphTarget {
+            positions: positions.map_or(Vec::new(), |positions| {
+                read_accessor(positions.map(glm::Vec3::from), count)
+            }),
+            normals: normals.map_or(Vec::new(), |normals| {
+                read_accessor(normals.map(glm::Vec3::from), count)
+            }),
+            tangents: tangents.map_or(Vec::new(), |tangents| {
+                read_accessor(tangents.map(glm::Vec3::from), count)
+            }),
+        })
+        .collect::<Vec<_>>()
+}
 
+// The primitive's buffers are created once it's uploaded
+fn prepare_primitive(vertex_set: VertexSet, indices: Option<Vec<u32>>) -> Primitive {
     Primitive {
-        vao,
-        num_indices: indices.len() as i32,
+        vao: VertexArrayObject::default(),
+        vbo: Buffer::default(),
+        ibo: None,
+        num_indices: indices.as_ref().map_or(0, |indices| indices.len() as i32),
+        num_vertices: vertex_set.vertices.len() as i32,
+        draw_mode: if indices.is_some() {
+            DrawMode::Elements
+        } else {
+            DrawMode::Arrays
+        },
+        attributes: vertex_set.attributes(),
+        mode: Mode::Triangles,
         material_index: None,
+        center: glm::Vec3::zeros(),
+        morph_targets: Vec::new(),
+        base_vertices: None,
+        morph_weights: Vec::new(),
+        pending_vertices: Some(vertex_set),
+        pending_indices: indices,
+        uploaded: false,
     }
 }
 
@@ -626,6 +1998,418 @@ pub fn calculate_global_transform(node_index: NodeIndex, graph: &NodeGraph) -> g
     indices
         .iter()
         .fold(glm::Mat4::identity(), |transform, index| {
-            transform * graph[*index].local_transform * graph[*index].animation_transform.matrix()
+            transform * graph[*index].local_matrix()
         })
 }
+
+// Recomputes the cached global transforms of dirty nodes and their descendants, parents first
+pub fn update_global_transforms(graph: &mut NodeGraph) {
+    if graph.node_count() == 0 {
+        return;
+    }
+    let mut dfs = Dfs::new(&*graph, NodeIndex::new(0));
+    while let Some(node_index) = dfs.next(&*graph) {
+        if !graph[node_index].dirty {
+            continue;
+        }
+
+        let parent_transform = graph
+            .neighbors_directed(node_index, Incoming)
+            .next()
+            .map_or_else(glm::Mat4::identity, |parent| graph[parent].global_transform);
+        let node = &mut graph[node_index];
+        node.global_transform = parent_transform * node.local_matrix();
+        node.dirty = false;
+
+        let mut children = graph.neighbors_directed(node_index, Outgoing).detach();
+        while let Some(child) = children.next_node(&*graph) {
+            graph[child].dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_animations(path: &str) -> Vec<Animation> {
+        let (gltf, buffers, _) = gltf::import(path).expect("Couldn't import file!");
+        prepare_animations(&gltf, &buffers)
+    }
+
+    fn sample_translation(channel: &mut Channel, time: f32) -> glm::Vec3 {
+        match channel.sample(time) {
+            ChannelValue::Translation(translation) => translation,
+            value => panic!("Expected a translation, got {:?}", value),
+        }
+    }
+
+    fn sample_rotation(channel: &mut Channel, time: f32) -> glm::Quat {
+        match channel.sample(time) {
+            ChannelValue::Rotation(rotation) => rotation,
+            value => panic!("Expected a rotation, got {:?}", value),
+        }
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn step_holds_the_previous_key() {
+        let mut animations = load_animations("assets/models/InterpolationTest.glb");
+        let channel = &mut animations[6].channels[0];
+        assert_eq!(channel.interpolation, Interpolation::Step);
+
+        // Keys at 0.0, 0.458, 0.833, 1.292 and 1.708 seconds
+        assert_near(sample_translation(channel, 0.2).y, 6.665_226_5);
+        assert_near(sample_translation(channel, 0.458).y, 6.665_226_5);
+        assert_near(sample_translation(channel, 0.6).y, 10.0);
+        assert_near(sample_translation(channel, 1.0).y, 6.0);
+        assert_near(sample_translation(channel, 5.0).y, 6.0);
+    }
+
+    #[test]
+    fn step_switches_exactly_at_a_key() {
+        let mut animations = load_animations("assets/models/InterpolationTest.glb");
+        let channel = &mut animations[6].channels[0];
+        let inputs = channel.inputs.clone();
+
+        assert_near(sample_translation(channel, inputs[0]).y, 6.665_226_5);
+        assert_near(sample_translation(channel, inputs[1]).y, 10.0);
+        assert_near(sample_translation(channel, inputs[2]).y, 6.0);
+        // Searching backwards instead of reusing the previous keys
+        assert_near(sample_translation(channel, inputs[1]).y, 10.0);
+        assert_near(sample_translation(channel, inputs[3]).y, 10.0);
+    }
+
+    #[test]
+    fn cubic_spline_reads_tangent_value_tangent_triplets() {
+        let mut animations = load_animations("assets/models/InterpolationTest.glb");
+        let channel = &mut animations[7].channels[0];
+        assert_eq!(channel.interpolation, Interpolation::CubicSpline);
+        let inputs = channel.inputs.clone();
+
+        // The keys hit their values, not the tangents stored around them
+        let expected = [6.640_117, 10.0, 6.0, 10.0, 6.0];
+        for (input, expected) in inputs.iter().zip(expected.iter()) {
+            let translation = sample_translation(channel, *input);
+            assert_near(translation.x, 3.305_179_8);
+            assert_near(translation.y, *expected);
+        }
+
+        // The tangents are zero, so a quarter of the way between keys is
+        // weighted by the hermite basis as 0.84375 and 0.15625
+        let time = inputs[0] + (inputs[1] - inputs[0]) * 0.25;
+        assert_near(
+            sample_translation(channel, time).y,
+            6.640_117 * 0.843_75 + 10.0 * 0.156_25,
+        );
+    }
+
+    #[test]
+    fn cubic_spline_scales_tangents_by_the_key_delta() {
+        let mut animations = load_animations("assets/models/InterpolationTest.glb");
+        let channel = &mut animations[4].channels[0];
+        let inputs = channel.inputs.clone();
+        let key_delta = inputs[1] - inputs[0];
+
+        // Halfway between the first two keys, where the basis weights are 0.5, 0.125, 0.5 and -0.125
+        let start = glm::vec4(0.0, 0.0, 0.0, 1.0);
+        let start_out_tangent = glm::vec4(0.0, 0.0, 0.0, 1.0);
+        let end_in_tangent = glm::vec4(0.0, 0.0, -0.047_890_9, 0.998_852_67);
+        let end = glm::vec4(0.0, 0.0, -0.382_683_46, 0.923_879_56);
+        let expected = (start * 0.5
+            + start_out_tangent * (0.125 * key_delta)
+            + end * 0.5
+            + end_in_tangent * (-0.125 * key_delta))
+            .normalize();
+
+        let rotation = sample_rotation(channel, inputs[0] + key_delta * 0.5);
+        assert_near(rotation.coords.z, expected.z);
+        assert_near(rotation.coords.w, expected.w);
+    }
+
+    fn quaternion(angle: f32, axis: &glm::Vec3) -> glm::Vec4 {
+        glm::quat_angle_axis(angle, axis).coords
+    }
+
+    fn assert_quaternion_near(actual: &glm::Vec4, expected: &glm::Vec4) {
+        assert!(
+            (actual - expected).norm() < 1e-5,
+            "{:?} is not close to {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn slerp_halves_a_quarter_turn() {
+        let start = glm::vec4(0.0, 0.0, 0.0, 1.0);
+        let end = quaternion(std::f32::consts::FRAC_PI_2, &glm::Vec3::y());
+        let expected = quaternion(std::f32::consts::FRAC_PI_4, &glm::Vec3::y());
+        assert_quaternion_near(&slerp(&start, &end, 0.5), &expected);
+    }
+
+    #[test]
+    fn slerp_takes_the_shortest_path_for_negative_dot_products() {
+        let start = glm::vec4(0.0, 0.0, 0.0, 1.0);
+        let end = quaternion(std::f32::consts::FRAC_PI_2, &glm::Vec3::y());
+        let expected = quaternion(std::f32::consts::FRAC_PI_4, &glm::Vec3::y());
+        // The negated quaternion is the same rotation, reached the long way around
+        assert!(start.dot(&-end) < 0.0);
+        assert_quaternion_near(&slerp(&start, &-end, 0.5), &expected);
+    }
+
+    #[test]
+    fn slerp_lerps_nearly_parallel_quaternions() {
+        let start = glm::vec4(0.0, 0.0, 0.0, 1.0);
+        let end = quaternion(0.01, &glm::Vec3::x());
+        let halfway = slerp(&start, &end, 0.5);
+        assert!(halfway.iter().all(|component| component.is_finite()));
+        assert_quaternion_near(&halfway, &((start + end) * 0.5));
+        assert_quaternion_near(&halfway.normalize(), &quaternion(0.005, &glm::Vec3::x()));
+
+        assert_quaternion_near(&slerp(&start, &start, 0.5), &start);
+    }
+
+    #[test]
+    fn linear_rotations_sample_keyframe_midpoints() {
+        let mut animations = load_animations("assets/models/InterpolationTest.glb");
+        let channel = &mut animations[5].channels[0];
+        assert_eq!(channel.interpolation, Interpolation::Linear);
+        let inputs = channel.inputs.clone();
+
+        // The first two keys are the identity and an eighth of a turn about -z
+        let halfway = (inputs[0] + inputs[1]) * 0.5;
+        let expected = quaternion(std::f32::consts::PI / 8.0, &-glm::Vec3::z());
+        assert_quaternion_near(&sample_rotation(channel, halfway).coords, &expected);
+    }
+
+    #[test]
+    fn sampled_rotations_have_unit_length() {
+        let mut animations = load_animations("assets/models/InterpolationTest.glb");
+        for animation_index in &[3, 4, 5] {
+            let channel = &mut animations[*animation_index].channels[0];
+            for step in 0..=100 {
+                let rotation = sample_rotation(channel, step as f32 * 0.02);
+                assert_near(rotation.coords.norm(), 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn morph_weights_set_at_load_wait_for_the_upload() {
+        let (gltf, buffers, _) =
+            gltf::import("assets/models/SparseMorph.gltf").expect("Couldn't import file!");
+        let scenes = prepare_scenes(&gltf, &buffers);
+        let node = &scenes[0].node_graphs[0][NodeIndex::new(0)];
+        let primitive = &node.mesh.as_ref().expect("Couldn't get mesh!").primitives[0];
+
+        assert!(!primitive.is_uploaded());
+        assert_eq!(node.weights, vec![0.5]);
+        assert_eq!(primitive.morph_weights, node.weights);
+        assert!(primitive.pending_vertices.is_none());
+
+        let morphed = primitive
+            .morphed_vertices(&primitive.morph_weights)
+            .expect("Couldn't morph vertices!");
+        // Generating flat normals unwelds the quad, the last corner is the displaced vertex
+        assert_eq!(
+            morphed.vertices[5].position,
+            Some(glm::vec3(0.0, 1.25, 0.0))
+        );
+    }
+
+    fn assert_matrix_near(actual: &glm::Mat4, expected: &glm::Mat4) {
+        assert!(
+            (actual - expected).abs().max() < 1e-4,
+            "{:?} is not close to {:?}",
+            actual,
+            expected
+        );
+    }
+
+    // RiggedSimple's mesh is node 1, skinned to joints 2 and 3
+    fn rigged_mesh_handle(scene: &Scene) -> NodeHandle {
+        let graph = &scene.node_graphs[0];
+        let node_index = graph
+            .node_indices()
+            .find(|node_index| graph[*node_index].index == 1)
+            .expect("Couldn't find mesh node!");
+        NodeHandle {
+            graph_index: 0,
+            node_index,
+        }
+    }
+
+    #[test]
+    fn joint_palette_is_identity_in_the_bind_pose() {
+        let (gltf, buffers, _) =
+            gltf::import("assets/models/RiggedSimple.glb").expect("Couldn't import file!");
+        let scenes = prepare_scenes(&gltf, &buffers);
+        let palette = scenes[0].joint_matrices(rigged_mesh_handle(&scenes[0]));
+        assert_eq!(palette.len(), 2);
+        for joint_matrix in palette.iter() {
+            assert_matrix_near(joint_matrix, &glm::Mat4::identity());
+        }
+    }
+
+    #[test]
+    fn animated_components_replace_the_rest_pose() {
+        let mut asset = GltfAsset::import("assets/models/RiggedSimple.glb");
+        // The second key bends the upper joint, while the lower joint's keys match its rest pose
+        asset.sample_animation(0, 1.041_667);
+        let scene = &asset.scenes[0];
+        let palette = scene.joint_matrices(rigged_mesh_handle(scene));
+
+        assert_matrix_near(&palette[0], &glm::Mat4::identity());
+        // A rotation of about 34 degrees around x, pivoting on the upper joint
+        let expected = glm::Mat4::from_column_slice(&[
+            1.0,
+            0.0,
+            0.0,
+            0.0, //
+            0.0,
+            0.825_549,
+            -0.564_330_6,
+            0.0, //
+            0.0,
+            0.564_330_6,
+            0.825_549,
+            0.0, //
+            0.0,
+            0.001_073_5,
+            0.016_966_5,
+            1.0,
+        ]);
+        assert_matrix_near(&palette[1], &expected);
+    }
+
+    #[test]
+    fn blending_starts_from_the_rest_pose() {
+        let rest = Transform {
+            translation: Some(glm::vec3(1.0, 0.0, 0.0)),
+            rotation: Some(glm::quat_angle_axis(1.0, &glm::Vec3::y())),
+            scale: Some(glm::vec3(2.0, 2.0, 2.0)),
+        };
+        let animated = Transform {
+            translation: Some(glm::vec3(3.0, 0.0, 0.0)),
+            ..Transform::default()
+        };
+        let blended = Transform::default().blend(&animated, 0.5, &rest);
+        assert_eq!(blended.translation, Some(glm::vec3(2.0, 0.0, 0.0)));
+        assert_eq!(blended.rotation, rest.rotation);
+        assert_eq!(blended.scale, rest.scale);
+
+        let added = Transform::default().add(&animated, 0.5, &rest);
+        assert_eq!(added.translation, Some(glm::vec3(2.5, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn every_primitive_mode_reads_its_vertices_and_indices() {
+        // (file, mode, vertex count, index count)
+        let modes = [
+            ("Points", Mode::Points, 25, None),
+            ("Lines", Mode::Lines, 4, Some(10)),
+            ("LineLoop", Mode::LineLoop, 16, Some(16)),
+            ("LineStrip", Mode::LineStrip, 9, None),
+            ("TriangleStrip", Mode::TriangleStrip, 10, Some(10)),
+            ("TriangleFan", Mode::TriangleFan, 8, None),
+        ];
+        for (name, mode, vertex_count, index_count) in modes.iter() {
+            let path = format!("assets/models/Modes/{}.gltf", name);
+            let (gltf, buffers, _) = gltf::import(&path).expect("Couldn't import file!");
+            let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+            assert_eq!(primitive.mode(), *mode, "{}", name);
+
+            let (vertex_set, indices) = read_buffer_data(&primitive, &buffers);
+            assert_eq!(vertex_set.vertices.len(), *vertex_count, "{}", name);
+            assert_eq!(
+                indices.map(|indices| indices.len()),
+                *index_count,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn normalized_unsigned_bytes_are_divided_by_their_maximum() {
+        let (gltf, buffers, _) =
+            gltf::import("assets/models/NormalizedAttributes.gltf").expect("Couldn't import file!");
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        let (vertex_set, _) = read_buffer_data(&primitive, &buffers);
+        let vertex = &vertex_set.vertices[0];
+        assert_eq!(vertex.tex_coords_0, Some(glm::vec2(1.0, 0.0)));
+        assert_eq!(vertex.weights_0, Some(glm::vec4(1.0, 0.0, 0.0, 0.0)));
+        let vertex = &vertex_set.vertices[2];
+        assert_near(vertex.tex_coords_0.unwrap().y, 0.2);
+        assert_near(
+            vertex.weights_0.unwrap().x + vertex.weights_0.unwrap().y,
+            1.0,
+        );
+
+        let mut animations = prepare_animations(&gltf, &buffers);
+        match animations[0].channels[0].sample(1.0) {
+            ChannelValue::MorphTargetWeights(weights) => assert_eq!(weights, vec![1.0]),
+            value => panic!("Expected morph target weights, got {:?}", value),
+        }
+
+        // A half turn about y, stored as unsigned bytes
+        // Sampled rotations are normalized, so the keyframes are checked as they were read
+        match &animations[0].channels[1].transformations {
+            TransformationSet::Rotations(rotations) => assert_eq!(
+                *rotations,
+                vec![glm::vec4(0.0, 0.0, 0.0, 1.0), glm::vec4(0.0, 1.0, 0.0, 0.0)]
+            ),
+            transformations => panic!("Expected rotations, got {:?}", transformations),
+        }
+        match animations[0].channels[1].sample(1.0) {
+            ChannelValue::Rotation(rotation) => {
+                assert_eq!(rotation.coords, glm::vec4(0.0, 1.0, 0.0, 0.0))
+            }
+            value => panic!("Expected a rotation, got {:?}", value),
+        }
+    }
+
+    #[test]
+    fn vertex_colors_are_normalized_to_rgba() {
+        let (gltf, buffers, _) =
+            gltf::import("assets/models/VertexColors.gltf").expect("Couldn't import file!");
+        let mut primitives = gltf.meshes().next().unwrap().primitives();
+
+        // Unsigned byte RGB colors, which get an opaque alpha
+        let (vertex_set, _) = read_buffer_data(&primitives.next().unwrap(), &buffers);
+        let colors = vertex_set
+            .vertices
+            .iter()
+            .map(|vertex| vertex.color_0.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            colors,
+            vec![
+                glm::vec4(1.0, 0.0, 0.0, 1.0),
+                glm::vec4(0.0, 1.0, 0.0, 1.0),
+                glm::vec4(0.0, 0.0, 1.0, 1.0),
+            ]
+        );
+
+        // Unsigned short RGBA colors, which keep their own alpha
+        let (vertex_set, _) = read_buffer_data(&primitives.next().unwrap(), &buffers);
+        let colors = vertex_set
+            .vertices
+            .iter()
+            .map(|vertex| vertex.color_0.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(colors[0], glm::vec4(1.0, 0.0, 0.0, 0.0));
+        assert_eq!(colors[1].xyz(), glm::vec3(0.0, 1.0, 0.0));
+        assert_near(colors[1].w, 0.5);
+        assert_eq!(colors[2], glm::vec4(0.0, 0.0, 1.0, 1.0));
+    }
+}
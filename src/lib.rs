@@ -1,10 +1,16 @@
+pub mod animation;
+pub mod animator;
 pub mod app;
 pub mod buffer;
 pub mod camera;
 pub mod framebuffer;
 pub mod gltf;
+pub mod material;
 pub mod mesh;
+pub mod meshprocessing;
 pub mod model;
+pub mod renderqueue;
+pub mod resource;
 pub mod shader;
 pub mod shaderprogram;
 pub mod skybox;
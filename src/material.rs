@@ -0,0 +1,193 @@
+use crate::{gltf::Primitive, shaderprogram::ShaderProgram, texture::Texture};
+pub use gltf::material::AlphaMode;
+use nalgebra_glm as glm;
+use std::sync::Arc;
+
+// The texture units the pbr shader samples each material texture from
+pub const BASE_COLOR_UNIT: u32 = 0;
+pub const METALLIC_ROUGHNESS_UNIT: u32 = 1;
+pub const NORMAL_UNIT: u32 = 2;
+pub const OCCLUSION_UNIT: u32 = 3;
+pub const EMISSIVE_UNIT: u32 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MaterialTexture {
+    // The glTF texture, which pairs an image with a sampler
+    pub texture_index: usize,
+    // Indexes the asset's loaded images
+    pub image_index: usize,
+    // The texture coordinate set the texture is sampled with
+    pub tex_coord: u32,
+}
+
+impl MaterialTexture {
+    fn new(texture: &gltf::Texture, tex_coord: u32) -> Self {
+        MaterialTexture {
+            texture_index: texture.index(),
+            image_index: texture.source().index(),
+            tex_coord,
+        }
+    }
+}
+
+// A glTF metallic-roughness material
+#[derive(Debug, Clone)]
+pub struct Material {
+    pub name: Option<String>,
+    pub base_color_factor: glm::Vec4,
+    pub base_color_texture: Option<MaterialTexture>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    // Metalness is read from the blue channel and roughness from the green channel
+    pub metallic_roughness_texture: Option<MaterialTexture>,
+    pub normal_texture: Option<MaterialTexture>,
+    pub normal_scale: f32,
+    // Occlusion is read from the red channel
+    pub occlusion_texture: Option<MaterialTexture>,
+    pub occlusion_strength: f32,
+    pub emissive_texture: Option<MaterialTexture>,
+    pub emissive_factor: glm::Vec3,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+// The glTF default material, used by primitives that don't reference one
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: None,
+            base_color_factor: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_texture: None,
+            emissive_factor: glm::Vec3::zeros(),
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    pub fn from_gltf(material: &gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let normal_texture = material.normal_texture();
+        let occlusion_texture = material.occlusion_texture();
+        Material {
+            name: material.name().map(str::to_string),
+            base_color_factor: glm::Vec4::from(pbr.base_color_factor()),
+            base_color_texture: pbr
+                .base_color_texture()
+                .map(|info| MaterialTexture::new(&info.texture(), info.tex_coord())),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .map(|info| MaterialTexture::new(&info.texture(), info.tex_coord())),
+            normal_texture: normal_texture
+                .as_ref()
+                .map(|info| MaterialTexture::new(&info.texture(), info.tex_coord())),
+            normal_scale: normal_texture.as_ref().map_or(1.0, |info| info.scale()),
+            occlusion_texture: occlusion_texture
+                .as_ref()
+                .map(|info| MaterialTexture::new(&info.texture(), info.tex_coord())),
+            occlusion_strength: occlusion_texture
+                .as_ref()
+                .map_or(1.0, |info| info.strength()),
+            emissive_texture: material
+                .emissive_texture()
+                .map(|info| MaterialTexture::new(&info.texture(), info.tex_coord())),
+            emissive_factor: glm::Vec3::from(material.emissive_factor()),
+            alpha_mode: material.alpha_mode(),
+            alpha_cutoff: material.alpha_cutoff(),
+            double_sided: material.double_sided(),
+        }
+    }
+
+    // Sets the pbr shader's material uniforms, binds the material's textures
+    // and sets the culling state it needs, blending is left to the RenderQueue
+    pub fn bind(
+        &self,
+        shader_program: &ShaderProgram,
+        textures: &[Arc<Texture>],
+        primitive: &Primitive,
+    ) {
+        shader_program.set_uniform_vec4(
+            "material.base_color_factor",
+            self.base_color_factor.as_slice(),
+        );
+        shader_program.set_uniform_float("material.metallic_factor", self.metallic_factor);
+        shader_program.set_uniform_float("material.roughness_factor", self.roughness_factor);
+        shader_program.set_uniform_float("material.normal_scale", self.normal_scale);
+        shader_program.set_uniform_float("material.occlusion_strength", self.occlusion_strength);
+        shader_program
+            .set_uniform_vec3("material.emissive_factor", self.emissive_factor.as_slice());
+        shader_program.set_uniform_int("material.alpha_mode", alpha_mode_index(self.alpha_mode));
+        shader_program.set_uniform_float("material.alpha_cutoff", self.alpha_cutoff);
+        shader_program.set_uniform_int("material.double_sided", self.double_sided as i32);
+
+        let slots = [
+            ("base_color", BASE_COLOR_UNIT, &self.base_color_texture),
+            (
+                "metallic_roughness",
+                METALLIC_ROUGHNESS_UNIT,
+                &self.metallic_roughness_texture,
+            ),
+            ("normal", NORMAL_UNIT, &self.normal_texture),
+            ("occlusion", OCCLUSION_UNIT, &self.occlusion_texture),
+            ("emissive", EMISSIVE_UNIT, &self.emissive_texture),
+        ];
+        for (name, unit, texture) in slots.iter() {
+            bind_texture(shader_program, name, *unit, texture, textures, primitive);
+        }
+
+        unsafe {
+            if self.double_sided {
+                gl::Disable(gl::CULL_FACE);
+            } else {
+                gl::Enable(gl::CULL_FACE);
+            }
+        }
+    }
+}
+
+// Matches the ALPHA_MODE constants in the pbr shader
+fn alpha_mode_index(alpha_mode: AlphaMode) -> i32 {
+    match alpha_mode {
+        AlphaMode::Opaque => 0,
+        AlphaMode::Mask => 1,
+        AlphaMode::Blend => 2,
+    }
+}
+
+fn bind_texture(
+    shader_program: &ShaderProgram,
+    name: &str,
+    unit: u32,
+    texture: &Option<MaterialTexture>,
+    textures: &[Arc<Texture>],
+    primitive: &Primitive,
+) {
+    let loaded_texture = texture
+        .as_ref()
+        .and_then(|texture| textures.get(texture.image_index));
+    shader_program.set_uniform_int(
+        &format!("material.has_{}_texture", name),
+        loaded_texture.is_some() as i32,
+    );
+    shader_program.set_uniform_int(&format!("material.{}_texture", name), unit as i32);
+    if let (Some(texture), Some(loaded_texture)) = (texture, loaded_texture) {
+        shader_program.set_uniform_int(
+            &format!("material.{}_tex_coord", name),
+            primitive.tex_coord_set(texture.tex_coord) as i32,
+        );
+        loaded_texture.bind(unit);
+    }
+}
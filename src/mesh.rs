@@ -9,18 +9,37 @@ pub struct Vertex {
     position: glm::Vec3,
     normal: glm::Vec3,
     tex_coords: glm::Vec2,
+    tangent: glm::Vec4,
 }
 
 impl Vertex {
-    pub fn new(position: glm::Vec3, normal: glm::Vec3, tex_coords: glm::Vec2) -> Self {
+    pub fn new(
+        position: glm::Vec3,
+        normal: glm::Vec3,
+        tex_coords: glm::Vec2,
+        tangent: glm::Vec4,
+    ) -> Self {
         Vertex {
             position,
             normal,
             tex_coords,
+            tangent,
         }
     }
 }
 
+impl VertexFormat for Vertex {
+    fn layout() -> VertexLayout {
+        let mut layout = VertexLayout::new();
+        layout
+            .float(Semantic::Position, 3)
+            .float(Semantic::Normal, 3)
+            .float(Semantic::TexCoords0, 2)
+            .float(Semantic::Tangent, 4);
+        layout
+    }
+}
+
 #[derive(Default)]
 pub struct Mesh {
     vao: VertexArrayObject,
@@ -46,9 +65,7 @@ impl Mesh {
         mesh.vbo.upload(&mesh.vao, DrawingHint::StaticDraw);
         mesh.ibo.add_data(&mesh.indices);
         mesh.ibo.upload(&mesh.vao, DrawingHint::StaticDraw);
-        mesh.vao.configure_attribute(0, 3, 8, 0);
-        mesh.vao.configure_attribute(1, 3, 8, 3);
-        mesh.vao.configure_attribute(2, 2, 8, 6);
+        mesh.vao.configure_layout(&Vertex::layout());
         mesh
     }
 
@@ -69,3 +86,14 @@ impl Mesh {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn layout_stride_matches_the_vertex_size() {
+        assert_eq!(Vertex::layout().stride() as usize, mem::size_of::<Vertex>());
+    }
+}
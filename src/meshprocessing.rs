@@ -0,0 +1,376 @@
+use nalgebra_glm as glm;
+
+// Indices are read as a triangle list, any trailing indices are ignored
+fn triangles(indices: &[u32]) -> impl Iterator<Item = [usize; 3]> + '_ {
+    indices.chunks_exact(3).map(|triangle| {
+        [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ]
+    })
+}
+
+// Degenerate vectors are left as zero instead of becoming NaN
+fn normalize_or_zero(vector: &glm::Vec3) -> glm::Vec3 {
+    let length = glm::length(vector);
+    if length > f32::EPSILON {
+        vector / length
+    } else {
+        glm::Vec3::zeros()
+    }
+}
+
+// Unnormalized, so summing these weights each face by its area
+fn face_normal(positions: &[glm::Vec3], triangle: &[usize; 3]) -> glm::Vec3 {
+    let [first, second, third] = *triangle;
+    glm::cross(
+        &(positions[second] - positions[first]),
+        &(positions[third] - positions[first]),
+    )
+}
+
+// Copies per-vertex values so every index refers to its own vertex
+pub fn unweld<T: Copy>(values: &[T], indices: &[u32]) -> Vec<T> {
+    indices
+        .iter()
+        .map(|index| values[*index as usize])
+        .collect::<Vec<_>>()
+}
+
+// Averages the normals of the triangles around each vertex, weighted by triangle area
+pub fn smooth_normals(positions: &[glm::Vec3], indices: &[u32]) -> Vec<glm::Vec3> {
+    let mut normals = vec![glm::Vec3::zeros(); positions.len()];
+    for triangle in triangles(indices) {
+        let normal = face_normal(positions, &triangle);
+        for index in triangle.iter() {
+            normals[*index] += normal;
+        }
+    }
+    normals.iter().map(normalize_or_zero).collect::<Vec<_>>()
+}
+
+// Gives every triangle corner its triangle's normal, returning one normal per index
+// Pair with unweld so that each corner has its own vertex
+pub fn flat_normals(positions: &[glm::Vec3], indices: &[u32]) -> Vec<glm::Vec3> {
+    let mut normals = Vec::with_capacity(indices.len());
+    for triangle in triangles(indices) {
+        let normal = normalize_or_zero(&face_normal(positions, &triangle));
+        normals.extend(&[normal, normal, normal]);
+    }
+    normals
+}
+
+// Tangents generated for a mesh, one per vertex after splitting
+// MikkTSpace can give the corners sharing a vertex different tangents, at UV seams and
+// mirrored UVs, so such vertices are split: each vertex past the original ones copies
+// the vertex that split_from names, and the indices are rewritten to refer to the copies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tangents {
+    pub tangents: Vec<glm::Vec4>,
+    pub split_from: Vec<u32>,
+}
+
+// Computes tangents with MikkTSpace, which is what glTF expects generated tangents to match
+// w holds the handedness, with bitangent = cross(normal, tangent.xyz) * w
+pub fn tangents(
+    positions: &[glm::Vec3],
+    normals: &[glm::Vec3],
+    tex_coords: &[glm::Vec2],
+    indices: &mut [u32],
+) -> Tangents {
+    let corner_tangents = corner_tangents(positions, normals, tex_coords, indices);
+
+    // Vertices outside of any usable triangle keep a tangent orthogonal to their normal
+    let mut tangents = normals
+        .iter()
+        .map(|normal| {
+            let tangent = perpendicular(normal);
+            glm::vec4(tangent.x, tangent.y, tangent.z, 1.0)
+        })
+        .collect::<Vec<_>>();
+    let mut split_from = Vec::new();
+
+    // Each tangent given to an original vertex, with the vertex that holds it
+    let mut assigned = vec![Vec::<(glm::Vec4, u32)>::new(); positions.len()];
+    for (corner, tangent) in corner_tangents.iter().enumerate() {
+        let tangent = match tangent {
+            Some(tangent) => *tangent,
+            None => continue,
+        };
+        let vertex = indices[corner] as usize;
+        let existing = assigned[vertex]
+            .iter()
+            .find(|(assigned_tangent, _)| *assigned_tangent == tangent)
+            .map(|(_, index)| *index);
+        indices[corner] = match existing {
+            Some(index) => index,
+            None => {
+                let index = if assigned[vertex].is_empty() {
+                    tangents[vertex] = tangent;
+                    vertex as u32
+                } else {
+                    split_from.push(vertex as u32);
+                    tangents.push(tangent);
+                    (tangents.len() - 1) as u32
+                };
+                assigned[vertex].push((tangent, index));
+                index
+            }
+        };
+    }
+
+    Tangents {
+        tangents,
+        split_from,
+    }
+}
+
+// Appends a copy of each split vertex's value, keeping other vertex data in step with the tangents
+// Empty values are left empty
+pub fn split<T: Copy>(values: &mut Vec<T>, split_from: &[u32]) {
+    if values.is_empty() {
+        return;
+    }
+    for index in split_from.iter() {
+        values.push(values[*index as usize]);
+    }
+}
+
+// The tangent MikkTSpace gives each triangle corner, in index order
+fn corner_tangents(
+    positions: &[glm::Vec3],
+    normals: &[glm::Vec3],
+    tex_coords: &[glm::Vec2],
+    indices: &[u32],
+) -> Vec<Option<glm::Vec4>> {
+    let triangles = triangles(indices).collect::<Vec<_>>();
+    let mut geometry = TangentGeometry {
+        positions,
+        normals,
+        tex_coords,
+        corner_tangents: vec![None; triangles.len() * 3],
+        triangles,
+    };
+    bevy_mikktspace::generate_tangents(&mut geometry);
+    geometry.corner_tangents
+}
+
+struct TangentGeometry<'a> {
+    positions: &'a [glm::Vec3],
+    normals: &'a [glm::Vec3],
+    tex_coords: &'a [glm::Vec2],
+    triangles: Vec<[usize; 3]>,
+    corner_tangents: Vec<Option<glm::Vec4>>,
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.triangles.len()
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.triangles[face][vert]].into()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.triangles[face][vert]].into()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.tex_coords[self.triangles[face][vert]].into()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corner_tangents[face * 3 + vert] = Some(glm::Vec4::from(tangent));
+    }
+}
+
+// Any unit vector orthogonal to the normal, for vertices without usable texture coordinates
+fn perpendicular(normal: &glm::Vec3) -> glm::Vec3 {
+    let axis = if normal.x.abs() < 0.9 {
+        glm::Vec3::x()
+    } else {
+        glm::Vec3::y()
+    };
+    normalize_or_zero(&glm::cross(normal, &axis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit quad facing +z, with texture coordinates following x and y
+    fn quad() -> (Vec<glm::Vec3>, Vec<glm::Vec2>, Vec<u32>) {
+        let positions = vec![
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(1.0, 0.0, 0.0),
+            glm::vec3(1.0, 1.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+        ];
+        let tex_coords = positions.iter().map(|position| position.xy()).collect();
+        (positions, tex_coords, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    // A cube welded at its corners, so every corner vertex lies on a seam between faces
+    fn cube() -> (Vec<glm::Vec3>, Vec<glm::Vec2>, Vec<u32>) {
+        let positions = (0..8)
+            .map(|corner| {
+                let coordinate = |bit: u32| if corner & bit == 0 { -1.0 } else { 1.0 };
+                glm::vec3(coordinate(1), coordinate(2), coordinate(4))
+            })
+            .collect::<Vec<_>>();
+        let tex_coords = positions
+            .iter()
+            .map(|position| glm::vec2(position.x + 0.5 * position.z, position.y + 0.5 * position.x))
+            .collect();
+        #[rustfmt::skip]
+        let indices = vec![
+            0, 6, 2, 0, 4, 6, // -x
+            1, 7, 5, 1, 3, 7, // +x
+            0, 5, 4, 0, 1, 5, // -y
+            2, 7, 3, 2, 6, 7, // +y
+            0, 3, 1, 0, 2, 3, // -z
+            4, 7, 6, 4, 5, 7, // +z
+        ];
+        (positions, tex_coords, indices)
+    }
+
+    fn assert_near(actual: &glm::Vec4, expected: &glm::Vec4) {
+        assert!(
+            glm::distance(actual, expected) < 1e-5,
+            "{:?} is not close to {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn smooth_normals_face_out_of_the_quad_and_cube() {
+        let (positions, _, indices) = quad();
+        for normal in smooth_normals(&positions, &indices).iter() {
+            assert_eq!(*normal, glm::vec3(0.0, 0.0, 1.0));
+        }
+
+        let (positions, _, indices) = cube();
+        for (normal, position) in smooth_normals(&positions, &indices)
+            .iter()
+            .zip(positions.iter())
+        {
+            assert!((glm::length(normal) - 1.0).abs() < 1e-5);
+            // Each corner's normal points away from the center, into its own octant
+            for axis in 0..3 {
+                assert!(normal[axis] * position[axis] > 0.0, "{:?}", normal);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_normals_give_every_corner_its_face_normal() {
+        let (positions, _, indices) = quad();
+        let normals = flat_normals(&positions, &indices);
+        assert_eq!(normals, vec![glm::vec3(0.0, 0.0, 1.0); 6]);
+
+        let (positions, _, indices) = cube();
+        let normals = flat_normals(&positions, &indices);
+        assert_eq!(normals.len(), indices.len());
+        for (face, normal) in normals.chunks_exact(6).enumerate() {
+            let mut expected = glm::Vec3::zeros();
+            expected[face / 2] = if face % 2 == 0 { -1.0 } else { 1.0 };
+            assert!(
+                normal.iter().all(|normal| *normal == expected),
+                "{:?}",
+                normal
+            );
+        }
+    }
+
+    #[test]
+    fn quad_tangents_follow_the_texture_coordinates() {
+        let (positions, tex_coords, mut indices) = quad();
+        let normals = vec![glm::vec3(0.0, 0.0, 1.0); 4];
+        let generated = tangents(&positions, &normals, &tex_coords, &mut indices);
+        assert!(generated.split_from.is_empty());
+        assert_eq!(indices, vec![0, 1, 2, 0, 2, 3]);
+        for tangent in generated.tangents.iter() {
+            assert_near(tangent, &glm::vec4(1.0, 0.0, 0.0, 1.0));
+        }
+
+        // Flipping v mirrors the bitangent
+        let tex_coords = tex_coords
+            .iter()
+            .map(|tex_coords| glm::vec2(tex_coords.x, 1.0 - tex_coords.y))
+            .collect::<Vec<_>>();
+        let mut indices = quad().2;
+        let generated = tangents(&positions, &normals, &tex_coords, &mut indices);
+        for tangent in generated.tangents.iter() {
+            assert_near(tangent, &glm::vec4(1.0, 0.0, 0.0, -1.0));
+        }
+    }
+
+    #[test]
+    fn mirrored_quads_split_their_shared_edge() {
+        // Two quads sharing the edge at x = 1, with u running back the other way on the right
+        let positions: Vec<glm::Vec3> = vec![
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(1.0, 0.0, 0.0),
+            glm::vec3(1.0, 1.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            glm::vec3(2.0, 0.0, 0.0),
+            glm::vec3(2.0, 1.0, 0.0),
+        ];
+        let tex_coords = positions
+            .iter()
+            .map(|position| glm::vec2(1.0 - (position.x - 1.0).abs(), position.y))
+            .collect::<Vec<_>>();
+        let normals = vec![glm::vec3(0.0, 0.0, 1.0); 6];
+        let mut indices = vec![0, 1, 2, 0, 2, 3, 1, 4, 5, 1, 5, 2];
+        let generated = tangents(&positions, &normals, &tex_coords, &mut indices);
+
+        assert_eq!(generated.split_from, vec![1, 2]);
+        assert_eq!(indices, vec![0, 1, 2, 0, 2, 3, 6, 4, 5, 6, 5, 7]);
+        for index in indices[..6].iter() {
+            assert_near(
+                &generated.tangents[*index as usize],
+                &glm::vec4(1.0, 0.0, 0.0, 1.0),
+            );
+        }
+        for index in indices[6..].iter() {
+            assert_near(
+                &generated.tangents[*index as usize],
+                &glm::vec4(-1.0, 0.0, 0.0, -1.0),
+            );
+        }
+    }
+
+    #[test]
+    fn seamed_cube_corners_keep_their_own_tangents() {
+        let (positions, tex_coords, original_indices) = cube();
+        let normals = smooth_normals(&positions, &original_indices);
+        let expected = corner_tangents(&positions, &normals, &tex_coords, &original_indices);
+
+        let mut indices = original_indices.clone();
+        let generated = tangents(&positions, &normals, &tex_coords, &mut indices);
+        assert!(!generated.split_from.is_empty());
+        assert_eq!(
+            generated.tangents.len(),
+            positions.len() + generated.split_from.len()
+        );
+
+        let mut split_positions = positions.clone();
+        split(&mut split_positions, &generated.split_from);
+        for (corner, index) in indices.iter().enumerate() {
+            let index = *index as usize;
+            // Split vertices are copies of the vertex the corner referred to
+            assert_eq!(
+                split_positions[index],
+                positions[original_indices[corner] as usize]
+            );
+            assert_eq!(Some(generated.tangents[index]), expected[corner]);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::mesh::*;
+use crate::meshprocessing::*;
 use crate::texture::*;
 use nalgebra_glm as glm;
 use std::path::{Path, PathBuf};
@@ -16,23 +17,48 @@ impl Model {
         let mut meshes = Vec::new();
         for model in models.iter() {
             let mesh_data = &model.mesh;
+            let mut positions = mesh_data
+                .positions
+                .chunks_exact(3)
+                .map(glm::Vec3::from_column_slice)
+                .collect::<Vec<_>>();
+
+            // OBJ files may leave out normals and texture coordinates
+            let mut normals = if mesh_data.normals.is_empty() {
+                smooth_normals(&positions, &mesh_data.indices)
+            } else {
+                mesh_data
+                    .normals
+                    .chunks_exact(3)
+                    .map(glm::Vec3::from_column_slice)
+                    .collect::<Vec<_>>()
+            };
+            let mut tex_coords = if mesh_data.texcoords.is_empty() {
+                vec![glm::Vec2::zeros(); positions.len()]
+            } else {
+                mesh_data
+                    .texcoords
+                    .chunks_exact(2)
+                    .map(glm::Vec2::from_column_slice)
+                    .collect::<Vec<_>>()
+            };
+            // Vertices split at UV seams are appended, with the indices rewritten to use them
+            let mut indices = mesh_data.indices.clone();
+            let Tangents {
+                tangents,
+                split_from,
+            } = tangents(&positions, &normals, &tex_coords, &mut indices);
+            split(&mut positions, &split_from);
+            split(&mut normals, &split_from);
+            split(&mut tex_coords, &split_from);
+
             let mut vertices = Vec::new();
-            for index in 0..mesh_data.positions.len() / 3 {
+            for index in 0..positions.len() {
                 let vertex = Vertex::new(
-                    glm::vec3(
-                        mesh_data.positions[index * 3],
-                        mesh_data.positions[index * 3 + 1],
-                        mesh_data.positions[index * 3 + 2],
-                    ),
-                    glm::vec3(
-                        mesh_data.normals[index * 3],
-                        mesh_data.normals[index * 3 + 1],
-                        mesh_data.normals[index * 3 + 2],
-                    ),
-                    glm::vec2(
-                        mesh_data.texcoords[index * 2],
-                        mesh_data.texcoords[index * 2 + 1],
-                    ),
+                    positions[index],
+                    normals[index],
+                    tex_coords[index],
+                    tangents[index],
                 );
                 vertices.push(vertex);
             }
@@ -50,7 +76,7 @@ impl Model {
                 Texture::from_file(diffuse_texture_path.as_path().as_os_str().to_str().unwrap());
             textures.push(texture);
 
-            meshes.push(Mesh::new(vertices, mesh_data.indices.clone(), textures));
+            meshes.push(Mesh::new(vertices, indices, textures));
         }
         Model { meshes }
     }
@@ -0,0 +1,200 @@
+use crate::material::AlphaMode;
+use nalgebra_glm as glm;
+
+// Something to draw in the blend pass, with its distance in front of the camera
+#[derive(Debug, Clone)]
+pub struct BlendedItem<T> {
+    pub item: T,
+    pub depth: f32,
+}
+
+// Splits draws into opaque, alpha tested and blended passes
+// Opaque and masked items are drawn in the order they're pushed,
+// blended items are drawn back-to-front after everything else
+#[derive(Debug, Clone)]
+pub struct RenderQueue<T> {
+    opaque: Vec<T>,
+    masked: Vec<T>,
+    blended: Vec<BlendedItem<T>>,
+}
+
+impl<T> Default for RenderQueue<T> {
+    fn default() -> Self {
+        RenderQueue {
+            opaque: Vec::new(),
+            masked: Vec::new(),
+            blended: Vec::new(),
+        }
+    }
+}
+
+impl<T> RenderQueue<T> {
+    pub fn new() -> Self {
+        RenderQueue::default()
+    }
+
+    // The depth is only used to order blended items
+    pub fn push(&mut self, item: T, alpha_mode: AlphaMode, depth: f32) {
+        match alpha_mode {
+            AlphaMode::Opaque => self.opaque.push(item),
+            AlphaMode::Mask => self.masked.push(item),
+            AlphaMode::Blend => self.blended.push(BlendedItem { item, depth }),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.opaque.clear();
+        self.masked.clear();
+        self.blended.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.opaque.len() + self.masked.len() + self.blended.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn opaque(&self) -> &[T] {
+        &self.opaque
+    }
+
+    pub fn masked(&self) -> &[T] {
+        &self.masked
+    }
+
+    pub fn blended(&self) -> &[BlendedItem<T>] {
+        &self.blended
+    }
+
+    // Orders blended items from farthest to nearest, keeping the push order for equal depths
+    // NaN depths are drawn first, as if they were infinitely far away
+    pub fn sort(&mut self) {
+        let depth = |blended: &BlendedItem<T>| {
+            if blended.depth.is_nan() {
+                f32::INFINITY
+            } else {
+                blended.depth
+            }
+        };
+        self.blended
+            .sort_by(|first, second| depth(second).total_cmp(&depth(first)));
+    }
+
+    // Sorts the queue, then draws each pass with the blend and depth write state it needs
+    pub fn render<F>(&mut self, mut draw: F)
+    where
+        F: FnMut(&T),
+    {
+        self.sort();
+
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::DepthMask(gl::TRUE);
+        }
+        for item in self.opaque.iter().chain(self.masked.iter()) {
+            draw(item);
+        }
+
+        // Blended surfaces are still depth tested, but don't hide what's behind them
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
+        }
+        for blended in self.blended.iter() {
+            draw(&blended.item);
+        }
+
+        unsafe {
+            gl::Disable(gl::BLEND);
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+}
+
+// How far in front of the camera a world space position is
+pub fn view_depth(view: &glm::Mat4, position: &glm::Vec3) -> f32 {
+    let view_position = view * glm::vec4(position.x, position.y, position.z, 1.0);
+    // The camera looks down negative z in view space
+    -view_position.z
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blended_order(queue: &RenderQueue<&'static str>) -> Vec<&'static str> {
+        queue.blended().iter().map(|blended| blended.item).collect()
+    }
+
+    #[test]
+    fn items_are_split_by_alpha_mode() {
+        let mut queue = RenderQueue::new();
+        queue.push("opaque", AlphaMode::Opaque, 1.0);
+        queue.push("masked", AlphaMode::Mask, 1.0);
+        queue.push("blended", AlphaMode::Blend, 1.0);
+        assert_eq!(queue.opaque(), &["opaque"]);
+        assert_eq!(queue.masked(), &["masked"]);
+        assert_eq!(blended_order(&queue), vec!["blended"]);
+        assert_eq!(queue.len(), 3);
+
+        queue.clear();
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn blended_items_are_sorted_back_to_front() {
+        let mut queue = RenderQueue::new();
+        queue.push("near", AlphaMode::Blend, 1.0);
+        queue.push("far", AlphaMode::Blend, 10.0);
+        queue.push("behind", AlphaMode::Blend, -5.0);
+        queue.push("middle", AlphaMode::Blend, 5.0);
+        queue.sort();
+        assert_eq!(
+            blended_order(&queue),
+            vec!["far", "middle", "near", "behind"]
+        );
+    }
+
+    #[test]
+    fn equal_depths_keep_the_push_order() {
+        let mut queue = RenderQueue::new();
+        queue.push("first", AlphaMode::Blend, 2.0);
+        queue.push("far", AlphaMode::Blend, 3.0);
+        queue.push("second", AlphaMode::Blend, 2.0);
+        queue.push("third", AlphaMode::Blend, 2.0);
+        queue.sort();
+        assert_eq!(
+            blended_order(&queue),
+            vec!["far", "first", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn nan_depths_are_drawn_first() {
+        let mut queue = RenderQueue::new();
+        queue.push("near", AlphaMode::Blend, 1.0);
+        queue.push("nan", AlphaMode::Blend, f32::NAN);
+        queue.push("far", AlphaMode::Blend, 10.0);
+        queue.push("negative nan", AlphaMode::Blend, -f32::NAN);
+        queue.sort();
+        assert_eq!(
+            blended_order(&queue),
+            vec!["nan", "negative nan", "far", "near"]
+        );
+    }
+
+    #[test]
+    fn view_depth_is_the_distance_in_front_of_the_camera() {
+        let view = glm::look_at(
+            &glm::vec3(0.0, 0.0, 10.0),
+            &glm::vec3(0.0, 0.0, 0.0),
+            &glm::vec3(0.0, 1.0, 0.0),
+        );
+        assert!((view_depth(&view, &glm::vec3(0.0, 0.0, 0.0)) - 10.0).abs() < 1e-5);
+        assert!((view_depth(&view, &glm::vec3(3.0, 2.0, 4.0)) - 6.0).abs() < 1e-5);
+        assert!(view_depth(&view, &glm::vec3(0.0, 0.0, 20.0)) < 0.0);
+    }
+}
@@ -0,0 +1,219 @@
+use gl::types::*;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex, MutexGuard,
+};
+
+// Every kind of GL object the crate owns
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+    Shader,
+    ShaderProgram,
+    VertexArray,
+    Framebuffer,
+    Renderbuffer,
+}
+
+impl ResourceKind {
+    fn index(self) -> usize {
+        match self {
+            ResourceKind::Buffer => 0,
+            ResourceKind::Texture => 1,
+            ResourceKind::Shader => 2,
+            ResourceKind::ShaderProgram => 3,
+            ResourceKind::VertexArray => 4,
+            ResourceKind::Framebuffer => 5,
+            ResourceKind::Renderbuffer => 6,
+        }
+    }
+}
+
+// How many objects of each kind have been created and not released yet
+static LIVE: [AtomicUsize; 7] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+// Released objects waiting for the thread that owns the GL context to delete them
+static PENDING: Mutex<Vec<(ResourceKind, GLuint)>> = Mutex::new(Vec::new());
+
+fn pending() -> MutexGuard<'static, Vec<(ResourceKind, GLuint)>> {
+    // The queue is still valid if a thread panicked while holding it,
+    // and handles are dropped while unwinding
+    PENDING
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Called when an object is created
+// Id 0 is what defaulted handles hold before creating anything, so it is ignored here and in release
+pub fn track(kind: ResourceKind, id: GLuint) {
+    if id != 0 {
+        LIVE[kind.index()].fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// Called when an object is dropped, which may happen on any thread,
+// so the object is only queued for deletion
+pub fn release(kind: ResourceKind, id: GLuint) {
+    if id == 0 {
+        return;
+    }
+    LIVE[kind.index()].fetch_sub(1, Ordering::SeqCst);
+    pending().push((kind, id));
+}
+
+// Deletes every released object, must be called on the thread that owns the GL context
+pub fn delete_pending() {
+    let released = pending().drain(..).collect::<Vec<_>>();
+    for (kind, id) in released {
+        unsafe {
+            match kind {
+                ResourceKind::Buffer => gl::DeleteBuffers(1, &id),
+                ResourceKind::Texture => gl::DeleteTextures(1, &id),
+                ResourceKind::Shader => gl::DeleteShader(id),
+                ResourceKind::ShaderProgram => gl::DeleteProgram(id),
+                ResourceKind::VertexArray => gl::DeleteVertexArrays(1, &id),
+                ResourceKind::Framebuffer => gl::DeleteFramebuffers(1, &id),
+                ResourceKind::Renderbuffer => gl::DeleteRenderbuffers(1, &id),
+            }
+        }
+    }
+}
+
+pub fn live_count(kind: ResourceKind) -> usize {
+    LIVE[kind.index()].load(Ordering::SeqCst)
+}
+
+pub fn pending_count() -> usize {
+    pending().len()
+}
+
+// Loading GL functions replaces them for the whole process,
+// so tests that mock them hold this lock for as long as they use the mocks
+#[cfg(test)]
+pub(crate) fn lock_mock_gl() -> MutexGuard<'static, ()> {
+    static MOCK_GL: Mutex<()> = Mutex::new(());
+    MOCK_GL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::*, vao::*};
+    use std::{os::raw::c_void, slice};
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+    static DELETED: Mutex<Vec<GLuint>> = Mutex::new(Vec::new());
+
+    extern "system" fn generate_objects(count: GLsizei, ids: *mut GLuint) {
+        let ids = unsafe { slice::from_raw_parts_mut(ids, count as usize) };
+        for id in ids.iter_mut() {
+            *id = NEXT_ID.fetch_add(1, Ordering::SeqCst) as GLuint;
+        }
+    }
+
+    extern "system" fn delete_objects(count: GLsizei, ids: *const GLuint) {
+        let ids = unsafe { slice::from_raw_parts(ids, count as usize) };
+        DELETED.lock().unwrap().extend_from_slice(ids);
+    }
+
+    extern "system" fn delete_object(id: GLuint) {
+        DELETED.lock().unwrap().push(id);
+    }
+
+    // Stands in for a context, handing out ids and recording the ones deleted
+    fn load_mock_functions() {
+        gl::GenBuffers::load_with(|_| generate_objects as *const c_void);
+        gl::GenVertexArrays::load_with(|_| generate_objects as *const c_void);
+        gl::DeleteBuffers::load_with(|_| delete_objects as *const c_void);
+        gl::DeleteTextures::load_with(|_| delete_objects as *const c_void);
+        gl::DeleteVertexArrays::load_with(|_| delete_objects as *const c_void);
+        gl::DeleteFramebuffers::load_with(|_| delete_objects as *const c_void);
+        gl::DeleteRenderbuffers::load_with(|_| delete_objects as *const c_void);
+        gl::DeleteShader::load_with(|_| delete_object as *const c_void);
+        gl::DeleteProgram::load_with(|_| delete_object as *const c_void);
+    }
+
+    const KINDS: [ResourceKind; 7] = [
+        ResourceKind::Buffer,
+        ResourceKind::Texture,
+        ResourceKind::Shader,
+        ResourceKind::ShaderProgram,
+        ResourceKind::VertexArray,
+        ResourceKind::Framebuffer,
+        ResourceKind::Renderbuffer,
+    ];
+
+    fn live_counts() -> Vec<usize> {
+        KINDS.iter().map(|kind| live_count(*kind)).collect()
+    }
+
+    // Other tests may hold objects of their own, so only changes in the counts are checked
+    #[test]
+    fn dropped_objects_are_counted_until_deleted() {
+        let _mock_gl = lock_mock_gl();
+        load_mock_functions();
+        let live_before = live_counts();
+        let pending_before = pending_count();
+
+        let first_id = NEXT_ID.load(Ordering::SeqCst) as GLuint;
+        let buffer = Buffer::new(BufferKind::Array);
+        let vao = VertexArrayObject::new();
+        assert_eq!(
+            live_count(ResourceKind::Buffer),
+            live_before[ResourceKind::Buffer.index()] + 1
+        );
+        assert_eq!(
+            live_count(ResourceKind::VertexArray),
+            live_before[ResourceKind::VertexArray.index()] + 1
+        );
+
+        // Kinds without a GL-free constructor are tracked with made up ids, well clear of the mock's
+        let others = [
+            (ResourceKind::Texture, GLuint::MAX - 4),
+            (ResourceKind::Shader, GLuint::MAX - 3),
+            (ResourceKind::ShaderProgram, GLuint::MAX - 2),
+            (ResourceKind::Framebuffer, GLuint::MAX - 1),
+            (ResourceKind::Renderbuffer, GLuint::MAX),
+        ];
+        for (kind, id) in others.iter() {
+            track(*kind, *id);
+            assert_eq!(live_count(*kind), live_before[kind.index()] + 1);
+        }
+
+        // Objects that failed to be created have no id and aren't tracked
+        track(ResourceKind::Texture, 0);
+        release(ResourceKind::Texture, 0);
+        assert_eq!(
+            live_count(ResourceKind::Texture),
+            live_before[ResourceKind::Texture.index()] + 1
+        );
+
+        drop(buffer);
+        drop(vao);
+        for (kind, id) in others.iter() {
+            release(*kind, *id);
+        }
+        assert_eq!(live_counts(), live_before);
+        assert_eq!(pending_count(), pending_before + 7);
+
+        delete_pending();
+        assert_eq!(pending_count(), 0);
+        let deleted = DELETED.lock().unwrap().clone();
+        let mut ours = vec![first_id, first_id + 1];
+        ours.extend(others.iter().map(|(_, id)| *id));
+        for id in ours.iter() {
+            assert!(deleted.contains(id), "{} wasn't deleted", id);
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use crate::shader::*;
+use crate::{resource::*, shader::*};
 pub use gl::types::*;
 use std::ffi::CString;
 use std::str;
 
+// Programs start out as id 0 before new creates one, and id 0 is never released
 #[derive(Default)]
 pub struct ShaderProgram {
     pub id: GLuint,
@@ -11,8 +12,10 @@ pub struct ShaderProgram {
 
 impl ShaderProgram {
     pub fn new() -> Self {
+        let id = unsafe { gl::CreateProgram() };
+        track(ResourceKind::ShaderProgram, id);
         ShaderProgram {
-            id: unsafe { gl::CreateProgram() },
+            id,
             shader_ids: Vec::new(),
         }
     }
@@ -68,11 +71,11 @@ impl ShaderProgram {
     pub fn link(&mut self) {
         unsafe {
             gl::LinkProgram(self.id);
-            for id in &self.shader_ids {
-                gl::DeleteShader(*id);
-            }
         }
-        self.shader_ids.clear();
+        // Linked programs don't need their shaders anymore
+        for id in self.shader_ids.drain(..) {
+            release(ResourceKind::Shader, id);
+        }
     }
 
     pub fn activate(&self) {
@@ -86,12 +89,6 @@ impl ShaderProgram {
         unsafe { gl::GetUniformLocation(self.id, name.as_ptr()) }
     }
 
-    pub fn free(&self) {
-        unsafe {
-            gl::DeleteProgram(self.id);
-        }
-    }
-
     // TODO: Add helpers for setting uniforms
     // Set floats
     // Vec2
@@ -157,7 +154,17 @@ impl ShaderProgram {
         unsafe {
             gl::AttachShader(self.id, shader.id);
         }
+        track(ResourceKind::Shader, shader.id);
         self.shader_ids.push(shader.id);
         self
     }
 }
+
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        for id in self.shader_ids.drain(..) {
+            release(ResourceKind::Shader, id);
+        }
+        release(ResourceKind::ShaderProgram, self.id);
+    }
+}
@@ -80,7 +80,9 @@ impl Skybox {
         skybox.vbo = Buffer::new(BufferKind::Array);
         skybox.vbo.add_data(VERTEX_POSITIONS);
         skybox.vbo.upload(&skybox.vao, DrawingHint::StaticDraw);
-        skybox.vao.configure_attribute(0, 3, 3, 0);
+        skybox
+            .vao
+            .configure_layout(VertexLayout::new().float(Semantic::Position, 3));
         skybox.texture = Texture::cubemap_from_files(paths);
         skybox
     }
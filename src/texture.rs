@@ -1,16 +1,208 @@
+use crate::resource::*;
 use gl::types::GLvoid;
 use image::{DynamicImage::*, GenericImageView};
 use std::collections::HashMap;
 use std::{
+    ffi::CStr,
     ptr,
-    sync::{Arc, RwLock},
+    sync::{Arc, OnceLock, RwLock},
 };
 
 pub type TextureCacheRef = Arc<RwLock<TextureCache>>;
 
+// From EXT_texture_filter_anisotropic, which the core 4.5 bindings don't include
+const TEXTURE_MAX_ANISOTROPY: u32 = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: u32 = 0x84FF;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WrappingMode {
+    ClampToEdge,
+    MirroredRepeat,
+    Repeat,
+}
+
+impl WrappingMode {
+    pub fn gl_enum(self) -> u32 {
+        match self {
+            WrappingMode::ClampToEdge => gl::CLAMP_TO_EDGE,
+            WrappingMode::MirroredRepeat => gl::MIRRORED_REPEAT,
+            WrappingMode::Repeat => gl::REPEAT,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MagFilter {
+    Nearest,
+    Linear,
+}
+
+impl MagFilter {
+    pub fn gl_enum(self) -> u32 {
+        match self {
+            MagFilter::Nearest => gl::NEAREST,
+            MagFilter::Linear => gl::LINEAR,
+        }
+    }
+}
+
+// The mipmap variants pick a mip level by the first filter,
+// and sample within or blend between levels by the second
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MinFilter {
+    Nearest,
+    Linear,
+    NearestMipmapNearest,
+    LinearMipmapNearest,
+    NearestMipmapLinear,
+    LinearMipmapLinear,
+}
+
+impl MinFilter {
+    pub fn gl_enum(self) -> u32 {
+        match self {
+            MinFilter::Nearest => gl::NEAREST,
+            MinFilter::Linear => gl::LINEAR,
+            MinFilter::NearestMipmapNearest => gl::NEAREST_MIPMAP_NEAREST,
+            MinFilter::LinearMipmapNearest => gl::LINEAR_MIPMAP_NEAREST,
+            MinFilter::NearestMipmapLinear => gl::NEAREST_MIPMAP_LINEAR,
+            MinFilter::LinearMipmapLinear => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    pub fn uses_mipmaps(self) -> bool {
+        self != MinFilter::Nearest && self != MinFilter::Linear
+    }
+}
+
+// Color data such as albedo and emissive maps is authored in sRGB and decoded
+// to linear values when sampled, while data like normal maps is already linear
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+}
+
+impl ColorSpace {
+    // The sized internal format to store pixels of the given format and data type in
+    // Single and dual channel data has no sRGB format, so it's always stored linearly
+    pub fn internal_format(self, pixel_format: u32, data_type: u32) -> u32 {
+        let wide = data_type == gl::UNSIGNED_SHORT;
+        match (self, pixel_format) {
+            (ColorSpace::Srgb, gl::RGB) | (ColorSpace::Srgb, gl::BGR) => gl::SRGB8,
+            (ColorSpace::Srgb, gl::RGBA) | (ColorSpace::Srgb, gl::BGRA) => gl::SRGB8_ALPHA8,
+            (_, gl::RED) if wide => gl::R16,
+            (_, gl::RED) => gl::R8,
+            (_, gl::RG) if wide => gl::RG16,
+            (_, gl::RG) => gl::RG8,
+            (_, gl::RGB) | (_, gl::BGR) if wide => gl::RGB16,
+            (_, gl::RGB) | (_, gl::BGR) => gl::RGB8,
+            (_, gl::RGBA) | (_, gl::BGRA) if wide => gl::RGBA16,
+            (_, gl::RGBA) | (_, gl::BGRA) => gl::RGBA8,
+            _ => pixel_format,
+        }
+    }
+}
+
+// How a texture is sampled: wrap modes per axis, filters and anisotropy
+// An anisotropy of 1.0 turns anisotropic filtering off
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SamplerDescriptor {
+    pub wrap_s: WrappingMode,
+    pub wrap_t: WrappingMode,
+    pub wrap_r: WrappingMode,
+    pub mag_filter: MagFilter,
+    pub min_filter: MinFilter,
+    pub anisotropy: f32,
+}
+
+impl Default for SamplerDescriptor {
+    fn default() -> Self {
+        SamplerDescriptor {
+            wrap_s: WrappingMode::Repeat,
+            wrap_t: WrappingMode::Repeat,
+            wrap_r: WrappingMode::Repeat,
+            mag_filter: MagFilter::Linear,
+            min_filter: MinFilter::Linear,
+            anisotropy: 1.0,
+        }
+    }
+}
+
+impl SamplerDescriptor {
+    // Uses the same wrapping mode on every axis
+    pub fn wrapping(mode: WrappingMode) -> Self {
+        SamplerDescriptor {
+            wrap_s: mode,
+            wrap_t: mode,
+            wrap_r: mode,
+            ..SamplerDescriptor::default()
+        }
+    }
+
+    // Sets the sampler state of the texture bound to the target
+    // Anisotropy is clamped to what the driver supports
+    pub fn apply(&self, target: u32) {
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, self.wrap_s.gl_enum() as i32);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, self.wrap_t.gl_enum() as i32);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_R, self.wrap_r.gl_enum() as i32);
+            gl::TexParameteri(
+                target,
+                gl::TEXTURE_MAG_FILTER,
+                self.mag_filter.gl_enum() as i32,
+            );
+            gl::TexParameteri(
+                target,
+                gl::TEXTURE_MIN_FILTER,
+                self.min_filter.gl_enum() as i32,
+            );
+
+            let max_anisotropy = max_anisotropy();
+            if max_anisotropy >= 1.0 {
+                let anisotropy = nalgebra::clamp(self.anisotropy, 1.0, max_anisotropy);
+                gl::TexParameterf(target, TEXTURE_MAX_ANISOTROPY, anisotropy);
+            }
+        }
+    }
+}
+
+// Zero when anisotropic filtering isn't supported
+// Only queried once, since asking without the extension raises INVALID_ENUM
+fn max_anisotropy() -> f32 {
+    static MAX_ANISOTROPY: OnceLock<f32> = OnceLock::new();
+    *MAX_ANISOTROPY.get_or_init(|| {
+        if !has_extension("GL_EXT_texture_filter_anisotropic")
+            && !has_extension("GL_ARB_texture_filter_anisotropic")
+        {
+            return 0.0;
+        }
+        let mut max_anisotropy = 0.0;
+        unsafe {
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max_anisotropy);
+        }
+        max_anisotropy
+    })
+}
+
+fn has_extension(name: &str) -> bool {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        (0..count.max(0) as u32).any(|index| {
+            let extension = gl::GetStringi(gl::EXTENSIONS, index);
+            !extension.is_null()
+                && CStr::from_ptr(extension as *const _).to_bytes() == name.as_bytes()
+        })
+    }
+}
+
+// Shares textures by key, such as a file path or a hash of the pixel data
+// Every user of a texture holds its own reference, so the cache can tell
+// which textures are no longer used anywhere else
 #[derive(Default)]
 pub struct TextureCache {
-    items: HashMap<String, Texture>,
+    items: HashMap<String, Arc<Texture>>,
 }
 
 impl TextureCache {
@@ -18,19 +210,57 @@ impl TextureCache {
         TextureCache::default()
     }
 
-    pub fn retrieve(&mut self, path: &str) -> &Texture {
-        if !self.items.contains_key(path) {
-            self.items
-                .insert(path.to_string(), Texture::from_file(path));
-        }
-        &self.items[path]
+    pub fn retrieve(&mut self, path: &str) -> Arc<Texture> {
+        self.retrieve_or_create(path, || Texture::from_file(path))
+    }
+
+    // Only creates the texture if nothing is cached under the key yet
+    pub fn retrieve_or_create<F>(&mut self, key: &str, create: F) -> Arc<Texture>
+    where
+        F: FnOnce() -> Texture,
+    {
+        self.items
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(create()))
+            .clone()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.items.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    // Drops the textures that only the cache still references
+    pub fn free_unused(&mut self) {
+        self.items
+            .retain(|_, texture| Arc::strong_count(texture) > 1);
     }
 }
 
-#[derive(Default)]
 pub struct Texture {
     id: u32,
     target: u32,
+    sampler: SamplerDescriptor,
+    color_space: ColorSpace,
+}
+
+// An id of 0 stands for a texture that was never generated, so it isn't released
+impl Default for Texture {
+    fn default() -> Self {
+        Texture {
+            id: 0,
+            target: 0,
+            sampler: SamplerDescriptor::default(),
+            color_space: ColorSpace::Linear,
+        }
+    }
 }
 
 impl Texture {
@@ -39,21 +269,31 @@ impl Texture {
         unsafe {
             gl::GenTextures(1, &mut id);
         }
-        Texture { id, target }
+        track(ResourceKind::Texture, id);
+        Texture {
+            id,
+            target,
+            sampler: SamplerDescriptor::default(),
+            color_space: ColorSpace::Linear,
+        }
     }
 
     pub fn from_file(path: &str) -> Self {
+        Texture::from_file_with_color_space(path, ColorSpace::Linear)
+    }
+
+    pub fn from_file_with_color_space(path: &str, color_space: ColorSpace) -> Self {
         let mut texture = Texture::new(gl::TEXTURE_2D);
+        texture.color_space = color_space;
         texture.load_image(path, texture.target, true);
-        texture.set_wrapping_repeat();
         texture
     }
 
     pub fn cubemap_from_files(paths: &[String; 6]) -> Self {
         let mut texture = Texture::new(gl::TEXTURE_CUBE_MAP);
+        texture.sampler = SamplerDescriptor::wrapping(WrappingMode::ClampToEdge);
         for (offset, path) in paths.iter().enumerate() {
             texture.load_image(path, gl::TEXTURE_CUBE_MAP_POSITIVE_X + offset as u32, false);
-            texture.set_wrapping_clamp();
         }
         texture
     }
@@ -65,16 +305,30 @@ impl Texture {
         }
     }
 
-    pub fn free(&self) {
-        unsafe {
-            gl::DeleteTextures(1, &self.id);
-        }
-    }
-
     pub fn id(&self) -> u32 {
         self.id
     }
 
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    // Only affects data loaded afterwards
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+    }
+
+    pub fn sampler(&self) -> &SamplerDescriptor {
+        &self.sampler
+    }
+
+    // Kept for any data loaded afterwards
+    pub fn set_sampler(&mut self, sampler: SamplerDescriptor) {
+        self.sampler = sampler;
+        self.bind(0);
+        self.sampler.apply(self.target);
+    }
+
     pub fn load_data(
         &mut self,
         width: u32,
@@ -82,6 +336,26 @@ impl Texture {
         pixels: &[u8],
         pixel_format: u32,
         target: u32,
+    ) {
+        self.load_data_of_type(
+            width,
+            height,
+            pixels,
+            pixel_format,
+            gl::UNSIGNED_BYTE,
+            target,
+        );
+    }
+
+    // For pixels with wider channels, such as gl::UNSIGNED_SHORT data
+    pub fn load_data_of_type(
+        &mut self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        pixel_format: u32,
+        data_type: u32,
+        target: u32,
     ) {
         self.bind(0);
         let image_data = if pixels.is_empty() {
@@ -93,17 +367,17 @@ impl Texture {
             gl::TexImage2D(
                 target,
                 0,
-                pixel_format as i32,
+                self.color_space.internal_format(pixel_format, data_type) as i32,
                 width as i32,
                 height as i32,
                 0,
                 pixel_format,
-                gl::UNSIGNED_BYTE,
+                data_type,
                 image_data as *const GLvoid,
             );
             gl::GenerateMipmap(target);
         }
-        self.set_filtering_linear();
+        self.sampler.apply(self.target);
     }
 
     fn load_image(&mut self, path: &str, target: u32, flipv: bool) {
@@ -127,27 +401,10 @@ impl Texture {
             target,
         );
     }
+}
 
-    fn set_wrapping_clamp(&mut self) {
-        unsafe {
-            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
-            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
-        }
-    }
-
-    fn set_wrapping_repeat(&mut self) {
-        unsafe {
-            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-            gl::TexParameteri(self.target, gl::TEXTURE_WRAP_R, gl::REPEAT as i32);
-        }
-    }
-
-    fn set_filtering_linear(&mut self) {
-        unsafe {
-            gl::TexParameteri(self.target, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-            gl::TexParameteri(self.target, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-        }
+impl Drop for Texture {
+    fn drop(&mut self) {
+        release(ResourceKind::Texture, self.id);
     }
 }
@@ -1,6 +1,182 @@
+use crate::resource::*;
 use gl::types::*;
 use std::mem;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ComponentType {
+    Byte,
+    UnsignedByte,
+    Short,
+    UnsignedShort,
+    Int,
+    UnsignedInt,
+    Float,
+}
+
+impl ComponentType {
+    // The size of one component in bytes
+    pub fn size(self) -> u32 {
+        let size = match self {
+            ComponentType::Byte => mem::size_of::<GLbyte>(),
+            ComponentType::UnsignedByte => mem::size_of::<GLubyte>(),
+            ComponentType::Short => mem::size_of::<GLshort>(),
+            ComponentType::UnsignedShort => mem::size_of::<GLushort>(),
+            ComponentType::Int => mem::size_of::<GLint>(),
+            ComponentType::UnsignedInt => mem::size_of::<GLuint>(),
+            ComponentType::Float => mem::size_of::<GLfloat>(),
+        };
+        size as u32
+    }
+
+    pub fn gl_type(self) -> GLenum {
+        match self {
+            ComponentType::Byte => gl::BYTE,
+            ComponentType::UnsignedByte => gl::UNSIGNED_BYTE,
+            ComponentType::Short => gl::SHORT,
+            ComponentType::UnsignedShort => gl::UNSIGNED_SHORT,
+            ComponentType::Int => gl::INT,
+            ComponentType::UnsignedInt => gl::UNSIGNED_INT,
+            ComponentType::Float => gl::FLOAT,
+        }
+    }
+}
+
+// What an attribute holds, which decides the shader location it is bound to
+//   0 Position   3 Joints0    6 TexCoords1
+//   1 Normal     4 Weights0   7 Color0
+//   2 TexCoords0 5 Tangent
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Semantic {
+    Position,
+    Normal,
+    TexCoords0,
+    Joints0,
+    Weights0,
+    Tangent,
+    TexCoords1,
+    Color0,
+    // Any other data, bound to the given location
+    Custom(u32),
+}
+
+impl Semantic {
+    pub fn location(self) -> u32 {
+        match self {
+            Semantic::Position => 0,
+            Semantic::Normal => 1,
+            Semantic::TexCoords0 => 2,
+            Semantic::Joints0 => 3,
+            Semantic::Weights0 => 4,
+            Semantic::Tangent => 5,
+            Semantic::TexCoords1 => 6,
+            Semantic::Color0 => 7,
+            Semantic::Custom(location) => location,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VertexLayoutAttribute {
+    pub semantic: Semantic,
+    pub component_type: ComponentType,
+    pub count: u32,
+    // Maps integer components to [0, 1] or [-1, 1] when read as floats
+    pub normalized: bool,
+    // Read through an ivec or uvec input instead of being converted to floats
+    pub integer: bool,
+    // Zero advances the attribute per vertex, otherwise once every divisor instances
+    pub divisor: u32,
+    // The offset from the start of the vertex in bytes
+    pub offset: u32,
+}
+
+impl VertexLayoutAttribute {
+    pub fn size(&self) -> u32 {
+        self.count * self.component_type.size()
+    }
+}
+
+// Describes the interleaved attributes of one vertex buffer, in the order they are stored
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VertexLayout {
+    attributes: Vec<VertexLayoutAttribute>,
+    // The size of one vertex in bytes
+    stride: u32,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        VertexLayout::default()
+    }
+
+    pub fn float(&mut self, semantic: Semantic, count: u32) -> &mut Self {
+        self.attribute(semantic, ComponentType::Float, count, false, false)
+    }
+
+    pub fn normalized(
+        &mut self,
+        semantic: Semantic,
+        component_type: ComponentType,
+        count: u32,
+    ) -> &mut Self {
+        self.attribute(semantic, component_type, count, true, false)
+    }
+
+    pub fn integer(
+        &mut self,
+        semantic: Semantic,
+        component_type: ComponentType,
+        count: u32,
+    ) -> &mut Self {
+        self.attribute(semantic, component_type, count, false, true)
+    }
+
+    // Appends an attribute after the ones already in the layout
+    pub fn attribute(
+        &mut self,
+        semantic: Semantic,
+        component_type: ComponentType,
+        count: u32,
+        normalized: bool,
+        integer: bool,
+    ) -> &mut Self {
+        let attribute = VertexLayoutAttribute {
+            semantic,
+            component_type,
+            count,
+            normalized,
+            integer,
+            divisor: 0,
+            offset: self.stride,
+        };
+        self.stride += attribute.size();
+        self.attributes.push(attribute);
+        self
+    }
+
+    // Advances every attribute in the layout once every divisor instances
+    pub fn per_instance(&mut self, divisor: u32) -> &mut Self {
+        for attribute in self.attributes.iter_mut() {
+            attribute.divisor = divisor;
+        }
+        self
+    }
+
+    pub fn attributes(&self) -> &[VertexLayoutAttribute] {
+        &self.attributes
+    }
+
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+}
+
+// Implemented by #[repr(C)] vertex structs, listing their fields in declaration order
+pub trait VertexFormat {
+    fn layout() -> VertexLayout;
+}
+
+// Defaults to id 0, a placeholder until new creates the array, and dropping it deletes nothing
 #[derive(Default, Debug)]
 pub struct VertexArrayObject {
     id: GLuint,
@@ -12,6 +188,7 @@ impl VertexArrayObject {
         unsafe {
             gl::GenVertexArrays(1, &mut id);
         }
+        track(ResourceKind::VertexArray, id);
         VertexArrayObject { id }
     }
 
@@ -21,19 +198,152 @@ impl VertexArrayObject {
         }
     }
 
+    // Configures tightly packed float data, with the total and offset counted in floats
     pub fn configure_attribute(&self, index: u32, count: u32, total: u32, offset: u32) {
+        let float_size = ComponentType::Float.size();
+        self.configure_float_attribute(
+            index,
+            count,
+            ComponentType::Float,
+            false,
+            total * float_size,
+            offset * float_size,
+        );
+    }
+
+    // Configures the layout's attributes on the array buffer that is currently bound
+    pub fn configure_layout(&self, layout: &VertexLayout) {
+        for attribute in layout.attributes() {
+            let location = attribute.semantic.location();
+            if attribute.integer {
+                self.configure_integer_attribute(
+                    location,
+                    attribute.count,
+                    attribute.component_type,
+                    layout.stride(),
+                    attribute.offset,
+                );
+            } else {
+                self.configure_float_attribute(
+                    location,
+                    attribute.count,
+                    attribute.component_type,
+                    attribute.normalized,
+                    layout.stride(),
+                    attribute.offset,
+                );
+            }
+            unsafe {
+                gl::VertexAttribDivisor(location, attribute.divisor);
+            }
+        }
+    }
+
+    // The shader reads the attribute as floats, and normalized integers are mapped to [0, 1] or [-1, 1]
+    // The stride and offset are in bytes
+    pub fn configure_float_attribute(
+        &self,
+        index: u32,
+        count: u32,
+        component_type: ComponentType,
+        normalized: bool,
+        stride: u32,
+        offset: u32,
+    ) {
         self.bind();
-        let float_size = mem::size_of::<GLfloat>() as u32;
+        let normalized = if normalized { gl::TRUE } else { gl::FALSE };
         unsafe {
             gl::EnableVertexAttribArray(index);
             gl::VertexAttribPointer(
                 index,
                 count as i32,
-                gl::FLOAT,
-                gl::FALSE,
-                (total * float_size) as i32,
-                (offset * float_size) as *const GLvoid,
+                component_type.gl_type(),
+                normalized,
+                stride as i32,
+                offset as *const GLvoid,
             );
         }
     }
+
+    // The shader reads the attribute as integers through an ivec or uvec input
+    // The stride and offset are in bytes
+    pub fn configure_integer_attribute(
+        &self,
+        index: u32,
+        count: u32,
+        component_type: ComponentType,
+        stride: u32,
+        offset: u32,
+    ) {
+        self.bind();
+        unsafe {
+            gl::EnableVertexAttribArray(index);
+            gl::VertexAttribIPointer(
+                index,
+                count as i32,
+                component_type.gl_type(),
+                stride as i32,
+                offset as *const GLvoid,
+            );
+        }
+    }
+}
+
+impl Drop for VertexArrayObject {
+    fn drop(&mut self) {
+        release(ResourceKind::VertexArray, self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_are_packed_one_after_another() {
+        let mut layout = VertexLayout::new();
+        layout
+            .float(Semantic::Position, 3)
+            .normalized(Semantic::Color0, ComponentType::UnsignedByte, 4)
+            .integer(Semantic::Joints0, ComponentType::UnsignedShort, 4)
+            .float(Semantic::TexCoords0, 2);
+
+        let offsets = layout
+            .attributes()
+            .iter()
+            .map(|attribute| attribute.offset)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 12, 16, 24]);
+        assert_eq!(layout.stride(), 32);
+
+        let color = layout.attributes()[1];
+        assert!(color.normalized && !color.integer);
+        let joints = layout.attributes()[2];
+        assert!(joints.integer && !joints.normalized);
+    }
+
+    #[test]
+    fn per_instance_sets_the_divisor_of_every_attribute_so_far() {
+        let mut layout = VertexLayout::new();
+        layout
+            .float(Semantic::Custom(8), 4)
+            .float(Semantic::Custom(9), 4)
+            .per_instance(2)
+            .float(Semantic::Custom(10), 4);
+
+        let divisors = layout
+            .attributes()
+            .iter()
+            .map(|attribute| attribute.divisor)
+            .collect::<Vec<_>>();
+        assert_eq!(divisors, vec![2, 2, 0]);
+        assert_eq!(layout.stride(), 48);
+    }
+
+    #[test]
+    fn custom_semantics_use_their_own_location() {
+        assert_eq!(Semantic::Position.location(), 0);
+        assert_eq!(Semantic::Color0.location(), 7);
+        assert_eq!(Semantic::Custom(12).location(), 12);
+    }
 }